@@ -0,0 +1,297 @@
+//! Authorize applications
+//!
+//! Mastodon uses OAuth 2 to authorize applications. This module implements the
+//! authorization code flow:
+//!
+//! 1. Register the application with [`register_app`].
+//! 2. Send the user to the URL built with [`authorize_url`].
+//! 3. Receive the authorization code, either by letting the user copy it with the
+//!    out-of-band redirect URI [`OOB_REDIRECT_URI`], or with a [`LoopbackListener`].
+//! 4. Exchange the code for an access token with [`exchange_code`].
+//!
+//! [`Pkce`] can be used to protect the authorization code, and [`revoke_token`]
+//! revokes an access token when the user logs out.
+
+mod loopback;
+mod pkce;
+
+use crate::client::transport::{Method, Transport};
+use crate::client::Client;
+use crate::Error;
+pub use loopback::{parse_redirect, LoopbackListener};
+pub use pkce::Pkce;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Out-of-band redirect URI
+///
+/// With this redirect URI, Mastodon displays the authorization code
+/// to the user, that can copy it in the application.
+pub const OOB_REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+
+/// A registered application
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct App {
+    /// Name of the application
+    pub name: String,
+    /// Website of the application
+    #[serde(default)]
+    pub website: Option<String>,
+    /// Client ID
+    pub client_id: String,
+    /// Client secret
+    pub client_secret: String,
+}
+
+/// An access token
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Token {
+    /// Access token
+    pub access_token: String,
+    /// Type of the token, usually `Bearer`
+    pub token_type: String,
+    /// Scopes granted to the token, separated by spaces
+    pub scope: String,
+    /// Creation date, as a UNIX timestamp
+    pub created_at: u64,
+}
+
+#[derive(Serialize)]
+struct AppRequest<'a> {
+    client_name: &'a str,
+    redirect_uris: String,
+    scopes: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    website: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct TokenRequest<'a> {
+    grant_type: &'a str,
+    code: &'a str,
+    client_id: &'a str,
+    client_secret: &'a str,
+    redirect_uri: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code_verifier: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct RevokeRequest<'a> {
+    client_id: &'a str,
+    client_secret: &'a str,
+    token: &'a str,
+}
+
+/// Register an application
+///
+/// `redirect_uris` are the URIs the user can be redirected to after the
+/// authorization, and `scopes` are the scopes the application can request.
+pub fn register_app<T>(
+    client: &Client<T>,
+    name: &str,
+    redirect_uris: &[&str],
+    scopes: &[&str],
+    website: Option<&str>,
+) -> Result<App, Error>
+where
+    T: Transport,
+{
+    let request = AppRequest {
+        client_name: name,
+        redirect_uris: redirect_uris.join("\n"),
+        scopes: scopes.join(" "),
+        website,
+    };
+    client.send_json(Method::Post, "api/v1/apps", &request)
+}
+
+/// Build the authorization URL
+///
+/// The user should open this URL in a browser, to authorize the application.
+/// `state` is returned unchanged with the redirect, and can be used to match
+/// the redirect with the request.
+pub fn authorize_url(
+    base: &Url,
+    app: &App,
+    redirect_uri: &str,
+    scopes: &[&str],
+    state: Option<&str>,
+    pkce: Option<&Pkce>,
+) -> Result<Url, Error> {
+    let mut url = base.join("oauth/authorize")?;
+    {
+        let mut query = url.query_pairs_mut();
+        query
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &app.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", &scopes.join(" "));
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+        if let Some(pkce) = pkce {
+            query
+                .append_pair("code_challenge", pkce.challenge())
+                .append_pair("code_challenge_method", pkce.challenge_method());
+        }
+    }
+    Ok(url)
+}
+
+/// Exchange an authorization code for an access token
+///
+/// `redirect_uri` must be the redirect URI used to build the authorization URL,
+/// and `pkce` must be provided if it was used to build it.
+pub fn exchange_code<T>(
+    client: &Client<T>,
+    app: &App,
+    code: &str,
+    redirect_uri: &str,
+    pkce: Option<&Pkce>,
+) -> Result<Token, Error>
+where
+    T: Transport,
+{
+    let request = TokenRequest {
+        grant_type: "authorization_code",
+        code,
+        client_id: &app.client_id,
+        client_secret: &app.client_secret,
+        redirect_uri,
+        code_verifier: pkce.map(Pkce::verifier),
+    };
+    client.send_json(Method::Post, "oauth/token", &request)
+}
+
+/// Revoke an access token
+pub fn revoke_token<T>(client: &Client<T>, app: &App, token: &str) -> Result<(), Error>
+where
+    T: Transport,
+{
+    let request = RevokeRequest {
+        client_id: &app.client_id,
+        client_secret: &app.client_secret,
+        token,
+    };
+    client
+        .send_json::<_, IgnoredAny>(Method::Post, "oauth/revoke", &request)
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::stub::StubTransport;
+    use serde_json::{json, Value};
+
+    const APP: &str = r#"{
+        "id": "563419",
+        "name": "crabodon",
+        "website": null,
+        "redirect_uri": "urn:ietf:wg:oauth:2.0:oob",
+        "client_id": "TWhM-tNSuncnqN7DBJmoyeLnk6K3iJJ71KKXxgL1hPM",
+        "client_secret": "ZEaFUFmF0umgBX1qKJDjaU99Q31lDkOU8NutzTOoliw",
+        "vapid_key": "BCk-QqERU0q-CfYZjcuB6lnyyOYfJ2AifKqfeGIm7Z-HiTU5T9eTG5GxVA0_OH5mMlI4UkkDTpaZwozy0TzdZ2M="
+    }"#;
+
+    fn client(transport: &StubTransport) -> Client<&StubTransport> {
+        Client::new(transport, Url::parse("https://mastodon.social").unwrap())
+    }
+
+    fn body(transport: &StubTransport, index: usize) -> Value {
+        let request = &transport.requests()[index];
+        serde_json::from_slice(request.body.as_ref().unwrap().as_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_register_app() {
+        let transport = StubTransport::default();
+        transport.push(200, APP);
+        let app = register_app(
+            &client(&transport),
+            "crabodon",
+            &[OOB_REDIRECT_URI],
+            &["read", "write"],
+            None,
+        )
+        .unwrap();
+        assert_eq!(app.client_id, "TWhM-tNSuncnqN7DBJmoyeLnk6K3iJJ71KKXxgL1hPM");
+        assert_eq!(
+            transport.requests()[0].url.as_str(),
+            "https://mastodon.social/api/v1/apps"
+        );
+        assert_eq!(
+            body(&transport, 0),
+            json!({
+                "client_name": "crabodon",
+                "redirect_uris": "urn:ietf:wg:oauth:2.0:oob",
+                "scopes": "read write",
+            })
+        );
+    }
+
+    #[test]
+    fn test_authorize_url() {
+        let app: App = serde_json::from_str(APP).unwrap();
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+        let url = authorize_url(
+            &Url::parse("https://mastodon.social").unwrap(),
+            &app,
+            OOB_REDIRECT_URI,
+            &["read", "write"],
+            Some("xyz"),
+            Some(&pkce),
+        )
+        .unwrap();
+        let expected = concat!(
+            "https://mastodon.social/oauth/authorize?response_type=code",
+            "&client_id=TWhM-tNSuncnqN7DBJmoyeLnk6K3iJJ71KKXxgL1hPM",
+            "&redirect_uri=urn%3Aietf%3Awg%3Aoauth%3A2.0%3Aoob",
+            "&scope=read+write&state=xyz",
+            "&code_challenge=E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+            "&code_challenge_method=S256"
+        );
+        assert_eq!(url.as_str(), expected);
+    }
+
+    #[test]
+    fn test_exchange_and_revoke() {
+        let app: App = serde_json::from_str(APP).unwrap();
+        let pkce = Pkce::from_verifier("verifier".to_string());
+        let transport = StubTransport::default();
+        transport.push(
+            200,
+            r#"{"access_token": "token", "token_type": "Bearer", "scope": "read write", "created_at": 1573979017}"#,
+        );
+        transport.push(200, "{}");
+        transport.push(
+            400,
+            r#"{"error": "invalid_grant", "error_description": "The provided authorization grant is invalid."}"#,
+        );
+
+        let client = client(&transport);
+        let token = exchange_code(&client, &app, "code", OOB_REDIRECT_URI, Some(&pkce)).unwrap();
+        assert_eq!(token.access_token, "token");
+        assert_eq!(body(&transport, 0)["code_verifier"], "verifier");
+        assert_eq!(body(&transport, 0)["grant_type"], "authorization_code");
+
+        revoke_token(&client, &app, "token").unwrap();
+        assert_eq!(transport.requests()[1].url.path(), "/oauth/revoke");
+        assert_eq!(body(&transport, 1)["token"], "token");
+
+        match exchange_code(&client, &app, "code", OOB_REDIRECT_URI, None) {
+            Err(Error::Authorization { error, description }) => {
+                assert_eq!(error, "invalid_grant");
+                assert_eq!(
+                    description.as_deref(),
+                    Some("The provided authorization grant is invalid.")
+                );
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Loopback redirect
+//!
+//! Desktop clients can receive the authorization code by redirecting the browser to
+//! a local HTTP server, as described in RFC 8252. This module offers
+//! [`LoopbackListener`], a tiny HTTP server that waits for this redirect.
+
+use crate::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+const CALLBACK_PATH: &str = "/callback";
+const RESPONSE_BODY: &str = "<html><body>You can now close this window.</body></html>";
+const NOT_FOUND_BODY: &str = "<html><body>Not found</body></html>";
+
+/// Delay between two checks for a connection, when a timeout is set
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long to wait for the request of a connection
+///
+/// Browsers may open connections in advance, and never send a request on them.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A listener for the loopback redirect
+#[derive(Debug)]
+pub struct LoopbackListener {
+    listener: TcpListener,
+    redirect_uri: String,
+    timeout: Option<Duration>,
+    connection_timeout: Duration,
+}
+
+impl LoopbackListener {
+    /// Listen on a random port of the loopback interface
+    pub fn bind() -> Result<Self, Error> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = listener.local_addr()?.port();
+        let redirect_uri = format!("http://127.0.0.1:{}{}", port, CALLBACK_PATH);
+        Ok(LoopbackListener {
+            listener,
+            redirect_uri,
+            timeout: None,
+            connection_timeout: CONNECTION_TIMEOUT,
+        })
+    }
+
+    /// Set how long to wait for the redirect
+    ///
+    /// By default, [`wait_for_code`](Self::wait_for_code) waits forever.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Redirect URI
+    ///
+    /// This URI should be used when registering the application, and
+    /// when building the authorization URL.
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    /// Wait for the redirect
+    ///
+    /// This method blocks until the browser is redirected, and returns the
+    /// authorization code. If `state` is provided, the state of the redirect
+    /// must match it.
+    ///
+    /// Other requests, like the browser asking for a favicon, are answered
+    /// with `404 Not Found` and ignored, and connections that do not send a
+    /// request within a few seconds are closed. If a timeout is set,
+    /// [`Error::Timeout`] is returned when no redirect was received in time.
+    pub fn wait_for_code(&self, state: Option<&str>) -> Result<String, Error> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        self.listener.set_nonblocking(deadline.is_some())?;
+        loop {
+            let stream = match self.accept(deadline)? {
+                Some(stream) => stream,
+                None => return Err(Error::Timeout),
+            };
+            // A connection that fails should not prevent the redirect
+            if let Ok(Some(url)) = handle(stream, self.connection_timeout, deadline) {
+                return parse_redirect(&url, state);
+            }
+        }
+    }
+
+    /// Accept a connection, or return `None` after the deadline
+    fn accept(&self, deadline: Option<Instant>) -> Result<Option<TcpStream>, Error> {
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return Ok(Some(self.listener.accept()?.0)),
+        };
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    return Ok(Some(stream));
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(None);
+                    }
+                    thread::sleep(POLL_INTERVAL.min(deadline - now));
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+}
+
+/// Handle a connection
+///
+/// Returns the URL of the request if it is the redirect.
+fn handle(
+    stream: TcpStream,
+    timeout: Duration,
+    deadline: Option<Instant>,
+) -> Result<Option<Url>, Error> {
+    let timeout = match deadline {
+        Some(deadline) => timeout.min(deadline.saturating_duration_since(Instant::now())),
+        None => timeout,
+    };
+    if timeout == Duration::from_secs(0) {
+        return Ok(None);
+    }
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Read the headers, so that the browser receives the response
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let target = request_line.split(' ').nth(1).unwrap_or("");
+    let url = Url::parse("http://127.0.0.1")?.join(target)?;
+    let is_redirect = url.path() == CALLBACK_PATH;
+    let (status, body) = if is_redirect {
+        ("200 OK", RESPONSE_BODY)
+    } else {
+        ("404 Not Found", NOT_FOUND_BODY)
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(if is_redirect { Some(url) } else { None })
+}
+
+/// Extract the authorization code from a redirect URL
+///
+/// This function is useful for clients that receive the redirect in another
+/// way, like a custom URI scheme. If `state` is provided, the state of the
+/// redirect must match it.
+pub fn parse_redirect(url: &Url, state: Option<&str>) -> Result<String, Error> {
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    if let Some(error) = param("error") {
+        return Err(Error::Authorization {
+            error,
+            description: param("error_description"),
+        });
+    }
+    if let Some(state) = state {
+        if param("state").as_deref() != Some(state) {
+            return Err(Error::Authorization {
+                error: "invalid_state".to_string(),
+                description: None,
+            });
+        }
+    }
+    param("code").ok_or_else(|| Error::Authorization {
+        error: "missing_code".to_string(),
+        description: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn address(listener: &LoopbackListener) -> String {
+        listener
+            .redirect_uri()
+            .trim_start_matches("http://")
+            .trim_end_matches(CALLBACK_PATH)
+            .to_string()
+    }
+
+    fn get(address: &str, target: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", target).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_loopback() {
+        let listener = LoopbackListener::bind().unwrap();
+        let address = address(&listener);
+        let browser = thread::spawn(move || {
+            let favicon = get(&address, "/favicon.ico");
+            let redirect = get(&address, "/callback?code=abc&state=xyz");
+            (favicon, redirect)
+        });
+        assert_eq!(listener.wait_for_code(Some("xyz")).unwrap(), "abc");
+        let (favicon, redirect) = browser.join().unwrap();
+        assert!(favicon.starts_with("HTTP/1.1 404 Not Found"));
+        assert!(redirect.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[test]
+    fn test_idle_connection() {
+        let mut listener = LoopbackListener::bind().unwrap();
+        listener.connection_timeout = Duration::from_millis(100);
+        let address = address(&listener);
+        let browser = thread::spawn(move || {
+            // A preconnection, without request
+            let idle = TcpStream::connect(&address).unwrap();
+            let redirect = get(&address, "/callback?code=abc");
+            drop(idle);
+            redirect
+        });
+        assert_eq!(listener.wait_for_code(None).unwrap(), "abc");
+        assert!(browser.join().unwrap().starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let listener = LoopbackListener::bind()
+            .unwrap()
+            .with_timeout(Duration::from_millis(200));
+        let address = address(&listener);
+        let browser = thread::spawn(move || get(&address, "/favicon.ico"));
+        assert!(matches!(listener.wait_for_code(None), Err(Error::Timeout)));
+        assert!(browser
+            .join()
+            .unwrap()
+            .starts_with("HTTP/1.1 404 Not Found"));
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let url = Url::parse("http://127.0.0.1:1234/callback?code=abc").unwrap();
+        assert_eq!(parse_redirect(&url, None).unwrap(), "abc");
+        assert!(matches!(
+            parse_redirect(&url, Some("xyz")),
+            Err(Error::Authorization { error, .. }) if error == "invalid_state"
+        ));
+
+        let url = Url::parse("http://127.0.0.1:1234/callback?error=access_denied").unwrap();
+        assert!(matches!(
+            parse_redirect(&url, None),
+            Err(Error::Authorization { error, .. }) if error == "access_denied"
+        ));
+    }
+}
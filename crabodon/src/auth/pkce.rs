@@ -0,0 +1,105 @@
+//! Proof Key for Code Exchange
+//!
+//! PKCE, described in RFC 7636, protects the authorization code flow against
+//! interception of the authorization code. A random code verifier is generated,
+//! and its SHA-256 hash, the code challenge, is sent with the authorization
+//! request. The code verifier is then sent when exchanging the code.
+
+use crate::Error;
+use sha2::{Digest, Sha256};
+
+/// Length of generated code verifiers
+const VERIFIER_LENGTH: usize = 64;
+
+/// Characters allowed in a code verifier
+const VERIFIER_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
+
+/// A PKCE code verifier and its challenge
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pkce {
+    verifier: String,
+    challenge: String,
+}
+
+impl Pkce {
+    /// Generate a random code verifier
+    ///
+    /// The code verifier is generated with the random number generator of
+    /// the operating system. This function fails with [`Error::Io`] if it is
+    /// not available.
+    pub fn generate() -> Result<Self, Error> {
+        // Bytes above the largest multiple of the number of characters are
+        // rejected, so that all characters are equally likely
+        let limit = 256 - 256 % VERIFIER_CHARS.len();
+        let mut verifier = String::with_capacity(VERIFIER_LENGTH);
+        let mut bytes = [0; VERIFIER_LENGTH];
+        while verifier.len() < VERIFIER_LENGTH {
+            getrandom::getrandom(&mut bytes)?;
+            let chars = bytes
+                .iter()
+                .filter(|byte| (**byte as usize) < limit)
+                .map(|byte| VERIFIER_CHARS[*byte as usize % VERIFIER_CHARS.len()] as char)
+                .take(VERIFIER_LENGTH - verifier.len());
+            verifier.extend(chars);
+        }
+        Ok(Self::from_verifier(verifier))
+    }
+
+    /// Create from an existing code verifier
+    pub fn from_verifier(verifier: String) -> Self {
+        let challenge =
+            base64::encode_config(Sha256::digest(verifier.as_bytes()), base64::URL_SAFE_NO_PAD);
+        Pkce {
+            verifier,
+            challenge,
+        }
+    }
+
+    /// Code verifier
+    ///
+    /// Sent when exchanging the authorization code.
+    pub fn verifier(&self) -> &str {
+        &self.verifier
+    }
+
+    /// Code challenge
+    ///
+    /// Sent with the authorization request.
+    pub fn challenge(&self) -> &str {
+        &self.challenge
+    }
+
+    /// Code challenge method
+    ///
+    /// Only `S256` is supported.
+    pub fn challenge_method(&self) -> &'static str {
+        "S256"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc_7636() {
+        // Example from appendix B of RFC 7636
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+        assert_eq!(
+            pkce.challenge(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+        assert_eq!(pkce.challenge_method(), "S256");
+    }
+
+    #[test]
+    fn test_generate() {
+        let pkce = Pkce::generate().unwrap();
+        assert_eq!(pkce.verifier().len(), VERIFIER_LENGTH);
+        assert!(pkce
+            .verifier()
+            .bytes()
+            .all(|byte| VERIFIER_CHARS.contains(&byte)));
+        assert_ne!(pkce, Pkce::generate().unwrap());
+    }
+}
@@ -0,0 +1,32 @@
+//! Exponential backoff
+//!
+//! Polling media, reconnecting streams and retrying rate limited requests all
+//! wait longer after each attempt. The delay doubles after each attempt, up to a
+//! maximum.
+
+use std::time::Duration;
+
+/// Delay before an attempt
+///
+/// `attempt` is the number of previous attempts, and the first attempt waits
+/// for `initial`.
+pub(crate) fn backoff(initial: Duration, max: Duration, attempt: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempt.min(16));
+    initial.checked_mul(factor).unwrap_or(max).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let delays = (0..6)
+            .map(|i| backoff(Duration::from_secs(1), Duration::from_secs(10), i).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+
+        let max = Duration::from_secs(u64::MAX);
+        assert_eq!(backoff(max, max, u32::MAX), max);
+    }
+}
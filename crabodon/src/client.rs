@@ -0,0 +1,763 @@
+//! Mastodon REST API client
+//!
+//! This module offers [`Client`], that calls Mastodon's REST API, and returns the
+//! [`entities`](crate::entities) it responds with. Content of statuses can then be
+//! processed with the [`content`](crate::content) module, through
+//! [`Html::parsed`](crate::entities::Html::parsed).
+//!
+//! The client does not perform HTTP requests itself, and uses an implementation of
+//! [`Transport`], see the [`transport`] module. Requests are blocking.
+//!
+//! Methods returning lists return the most recent items. Methods ending with `_pages`
+//! return an iterator over all pages, see the [`page`] module. Statuses are posted with
+//! [`Client::post_status`], see the [`post`] module.
+//!
+//! The client tracks rate limits, and delays or retries requests to stay within
+//! them, see the [`rate_limit`] module.
+
+pub mod media;
+pub mod page;
+pub mod post;
+pub mod rate_limit;
+pub mod transport;
+
+#[cfg(test)]
+pub(crate) mod stub;
+pub(crate) mod timestamp;
+
+use crate::entities::{Account, Context, MediaAttachment, Status};
+pub use crate::Error;
+pub use media::{MediaUpload, Progress};
+use page::{parse_link_header, Page, Pages, SearchKind};
+pub use post::{InstanceLimits, Posted, StatusBuilder};
+use rate_limit::{Bucket, RateLimiter};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+pub use transport::Transport;
+use transport::{Method, Request, Response};
+use url::Url;
+
+/// A Mastodon client
+///
+/// A client is bound to an instance, and optionally to an access token.
+#[derive(Clone, Debug)]
+pub struct Client<T> {
+    transport: T,
+    base: Url,
+    token: Option<String>,
+    rate_limiter: RateLimiter,
+}
+
+impl<T> Client<T>
+where
+    T: Transport,
+{
+    /// Create a client
+    ///
+    /// `base` is the URL of the instance, like `https://mastodon.social`. An
+    /// instance served under a path, like `https://example.com/mastodon`, is
+    /// supported with or without a trailing slash.
+    pub fn new(transport: T, mut base: Url) -> Self {
+        // Relative paths are joined to the base, which would drop its last
+        // segment without a trailing slash
+        if !base.path().ends_with('/') {
+            let path = format!("{}/", base.path());
+            base.set_path(&path);
+        }
+        Client {
+            transport,
+            base,
+            token: None,
+            rate_limiter: RateLimiter::default(),
+        }
+    }
+
+    /// Set the access token
+    ///
+    /// Requests are authenticated with this token.
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Set the rate limiter
+    ///
+    /// By default, the client uses a [`RateLimiter`] with the default policy.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Rate limiter of the client
+    ///
+    /// The rate limiter exposes the last known limits.
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// URL of the instance
+    pub fn base(&self) -> &Url {
+        &self.base
+    }
+
+    /// Get a status
+    pub fn status(&self, id: &str) -> Result<Status, Error> {
+        self.send_to(Method::Get, self.endpoint("api/v1/statuses", &[id])?)
+    }
+
+    /// Get the ancestors and descendants of a status
+    pub fn status_context(&self, id: &str) -> Result<Context, Error> {
+        self.send_to(
+            Method::Get,
+            self.endpoint("api/v1/statuses", &[id, "context"])?,
+        )
+    }
+
+    /// Post a status
+    ///
+    /// The status is validated against `limits` before being sent. Returns the
+    /// posted status, or the scheduled status if it was scheduled.
+    pub fn post_status(
+        &self,
+        status: &StatusBuilder,
+        limits: &InstanceLimits,
+    ) -> Result<Posted, Error> {
+        status.validate(limits)?;
+        let mut request = Request::new(Method::Post, self.url("api/v1/statuses", &[])?);
+        request
+            .headers
+            .push(("Content-Type".to_string(), "application/json".to_string()));
+        request
+            .headers
+            .push(("Idempotency-Key".to_string(), status.key().to_string()));
+        request.body = Some(serde_json::to_vec(status)?.into());
+        let response = self.execute_response(request)?;
+        Posted::from_json(status, &response.body)
+    }
+
+    /// Upload a media
+    ///
+    /// The file is streamed to the transport. When Mastodon processes the media
+    /// after the upload, the media is polled until it is ready. `progress` is
+    /// called as the upload progresses. Returns [`Error::Timeout`] if the
+    /// processing did not complete in time.
+    pub fn upload_media<R, F>(
+        &self,
+        upload: MediaUpload<R>,
+        progress: F,
+    ) -> Result<MediaAttachment, Error>
+    where
+        R: Read + Send + 'static,
+        F: FnMut(Progress) + Send + 'static,
+    {
+        let polling = upload.polling_policy();
+        let boundary = format!("crabodon-{}", post::generate_key());
+        let progress = Arc::new(Mutex::new(progress));
+        let (content_type, body) = upload.into_multipart(&boundary, progress.clone());
+        let mut request = Request::new(Method::Post, self.url("api/v2/media", &[])?);
+        request
+            .headers
+            .push(("Content-Type".to_string(), content_type));
+        request.body = Some(body);
+        let mut media: MediaAttachment = self.execute(request)?;
+
+        let url = self.endpoint("api/v1/media", &[&media.id])?;
+        let mut attempt = 0;
+        while media.url.is_none() {
+            if attempt >= polling.max_attempts {
+                return Err(Error::Timeout);
+            }
+            media::report(&progress, Progress::Processing { attempt });
+            self.rate_limiter.sleep(polling.delay(attempt));
+            media = self.send_to(Method::Get, url.clone())?;
+            attempt += 1;
+        }
+        media::report(&progress, Progress::Done);
+        Ok(media)
+    }
+
+    /// Get the limits of the instance
+    pub fn instance_limits(&self) -> Result<InstanceLimits, Error> {
+        self.get::<post::Instance>("api/v2/instance", &[])
+            .map(InstanceLimits::from)
+    }
+
+    /// Delete a status
+    ///
+    /// Returns the deleted status.
+    pub fn delete_status(&self, id: &str) -> Result<Status, Error> {
+        self.send_to(Method::Delete, self.endpoint("api/v1/statuses", &[id])?)
+    }
+
+    /// Favourite a status
+    pub fn favourite(&self, id: &str) -> Result<Status, Error> {
+        self.status_action(id, "favourite")
+    }
+
+    /// Remove a status from favourites
+    pub fn unfavourite(&self, id: &str) -> Result<Status, Error> {
+        self.status_action(id, "unfavourite")
+    }
+
+    /// Boost a status
+    pub fn reblog(&self, id: &str) -> Result<Status, Error> {
+        self.status_action(id, "reblog")
+    }
+
+    /// Undo the boost of a status
+    pub fn unreblog(&self, id: &str) -> Result<Status, Error> {
+        self.status_action(id, "unreblog")
+    }
+
+    /// Bookmark a status
+    pub fn bookmark(&self, id: &str) -> Result<Status, Error> {
+        self.status_action(id, "bookmark")
+    }
+
+    /// Remove a status from bookmarks
+    pub fn unbookmark(&self, id: &str) -> Result<Status, Error> {
+        self.status_action(id, "unbookmark")
+    }
+
+    /// Get an account
+    pub fn account(&self, id: &str) -> Result<Account, Error> {
+        self.send_to(Method::Get, self.endpoint("api/v1/accounts", &[id])?)
+    }
+
+    /// Get the account of the access token
+    pub fn verify_credentials(&self) -> Result<Account, Error> {
+        self.get("api/v1/accounts/verify_credentials", &[])
+    }
+
+    /// Get the statuses of an account
+    pub fn account_statuses(&self, id: &str) -> Result<Vec<Status>, Error> {
+        self.send_to(
+            Method::Get,
+            self.endpoint("api/v1/accounts", &[id, "statuses"])?,
+        )
+    }
+
+    /// Get the home timeline
+    pub fn home_timeline(&self) -> Result<Vec<Status>, Error> {
+        self.get("api/v1/timelines/home", &[])
+    }
+
+    /// Get the public timeline
+    ///
+    /// If `local` is true, only statuses from the instance are returned.
+    pub fn public_timeline(&self, local: bool) -> Result<Vec<Status>, Error> {
+        let query = if local { &[("local", "true")][..] } else { &[] };
+        self.get("api/v1/timelines/public", query)
+    }
+
+    /// Get the timeline of a hashtag
+    ///
+    /// `tag` is the name of the hashtag, without the # prefix.
+    pub fn hashtag_timeline(&self, tag: &str) -> Result<Vec<Status>, Error> {
+        self.send_to(Method::Get, self.endpoint("api/v1/timelines/tag", &[tag])?)
+    }
+
+    /// Iterate over the pages of the statuses of an account
+    pub fn account_statuses_pages(&self, id: &str) -> Result<Pages<'_, T, Status>, Error> {
+        let url = self.endpoint("api/v1/accounts", &[id, "statuses"])?;
+        Ok(Pages::new(self, url))
+    }
+
+    /// Iterate over the pages of the home timeline
+    pub fn home_timeline_pages(&self) -> Result<Pages<'_, T, Status>, Error> {
+        self.pages("api/v1/timelines/home", &[])
+    }
+
+    /// Iterate over the pages of the public timeline
+    ///
+    /// If `local` is true, only statuses from the instance are returned.
+    pub fn public_timeline_pages(&self, local: bool) -> Result<Pages<'_, T, Status>, Error> {
+        let query = if local { &[("local", "true")][..] } else { &[] };
+        self.pages("api/v1/timelines/public", query)
+    }
+
+    /// Iterate over the pages of the timeline of a hashtag
+    ///
+    /// `tag` is the name of the hashtag, without the # prefix.
+    pub fn hashtag_timeline_pages(&self, tag: &str) -> Result<Pages<'_, T, Status>, Error> {
+        let url = self.endpoint("api/v1/timelines/tag", &[tag])?;
+        Ok(Pages::new(self, url))
+    }
+
+    /// Iterate over the pages of search results
+    ///
+    /// Results of one `kind` are returned, as [`Account`]s, [`Status`]es or
+    /// [`Tag`](crate::entities::Tag)s. Search is paginated with an offset, and
+    /// Mastodon requires an access token to return more than the first page.
+    pub fn search_pages<R>(&self, query: &str, kind: SearchKind) -> Result<Pages<'_, T, R>, Error>
+    where
+        R: DeserializeOwned,
+    {
+        let url = self.url("api/v2/search", &[("q", query), ("type", kind.param())])?;
+        Ok(Pages::new(self, url).with_offset().in_field(kind.field()))
+    }
+
+    /// Iterate over the pages of any list endpoint
+    ///
+    /// `path` is the path of the endpoint, relative to the URL of the
+    /// instance, like `api/v1/favourites`.
+    pub fn pages<R>(&self, path: &str, query: &[(&str, &str)]) -> Result<Pages<'_, T, R>, Error>
+    where
+        R: DeserializeOwned,
+    {
+        Ok(Pages::new(self, self.url(path, query)?))
+    }
+
+    /// Fetch a page
+    ///
+    /// If `field` is set, the items are read from this field of the response.
+    pub(crate) fn page<R>(&self, url: Url, field: Option<&str>) -> Result<Page<R>, Error>
+    where
+        R: DeserializeOwned,
+    {
+        let response = self.execute_response(Request::new(Method::Get, url))?;
+        let links = response
+            .header("Link")
+            .map(parse_link_header)
+            .unwrap_or_default();
+        let items = match field {
+            Some(field) => {
+                let mut value = serde_json::from_slice::<serde_json::Value>(&response.body)?;
+                let items = value.get_mut(field).map(serde_json::Value::take);
+                serde_json::from_value(items.unwrap_or_default())?
+            }
+            None => serde_json::from_slice(&response.body)?,
+        };
+        Ok(Page {
+            items,
+            next: links.next,
+            prev: links.prev,
+        })
+    }
+
+    /// URL of an endpoint with parameters in its path, like ids
+    ///
+    /// `segments` are appended to `path`, and are percent-encoded, so that an
+    /// id cannot change the endpoint.
+    fn endpoint(&self, path: &str, segments: &[&str]) -> Result<Url, Error> {
+        let mut url = self.base.join(path)?;
+        url.path_segments_mut()
+            .map_err(|_| Error::Url(url::ParseError::RelativeUrlWithCannotBeABaseBase))?
+            .extend(segments);
+        Ok(url)
+    }
+
+    fn status_action(&self, id: &str, action: &str) -> Result<Status, Error> {
+        self.send_to(
+            Method::Post,
+            self.endpoint("api/v1/statuses", &[id, action])?,
+        )
+    }
+
+    fn get<R>(&self, path: &str, query: &[(&str, &str)]) -> Result<R, Error>
+    where
+        R: DeserializeOwned,
+    {
+        self.send(Method::Get, path, query)
+    }
+
+    fn send<R>(&self, method: Method, path: &str, query: &[(&str, &str)]) -> Result<R, Error>
+    where
+        R: DeserializeOwned,
+    {
+        self.send_to(method, self.url(path, query)?)
+    }
+
+    fn send_to<R>(&self, method: Method, url: Url) -> Result<R, Error>
+    where
+        R: DeserializeOwned,
+    {
+        self.execute(Request::new(method, url))
+    }
+
+    pub(crate) fn send_json<B, R>(&self, method: Method, path: &str, body: &B) -> Result<R, Error>
+    where
+        B: Serialize,
+        R: DeserializeOwned,
+    {
+        let mut request = Request::new(method, self.url(path, &[])?);
+        request
+            .headers
+            .push(("Content-Type".to_string(), "application/json".to_string()));
+        request.body = Some(serde_json::to_vec(body)?.into());
+        self.execute(request)
+    }
+
+    fn url(&self, path: &str, query: &[(&str, &str)]) -> Result<Url, Error> {
+        let mut url = self.base.join(path)?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        Ok(url)
+    }
+
+    fn execute<R>(&self, request: Request) -> Result<R, Error>
+    where
+        R: DeserializeOwned,
+    {
+        let response = self.execute_response(request)?;
+        Ok(serde_json::from_slice(&response.body)?)
+    }
+
+    fn execute_response(&self, mut request: Request) -> Result<Response, Error> {
+        if let Some(token) = &self.token {
+            request
+                .headers
+                .push(("Authorization".to_string(), format!("Bearer {}", token)));
+        }
+        let bucket = Bucket::of(&request);
+        let mut attempt = 0;
+        loop {
+            self.rate_limiter.sleep(self.rate_limiter.delay(bucket));
+            // Streamed bodies cannot be sent twice
+            let retry = request.try_clone();
+            let response = self.transport.send(request).map_err(Error::Transport)?;
+            self.rate_limiter.update(bucket, &response);
+            let delay = self.rate_limiter.retry_delay(bucket, &response, attempt);
+            match (retry, delay) {
+                (Some(retry), Some(delay)) => {
+                    self.rate_limiter.sleep(delay);
+                    request = retry;
+                    attempt += 1;
+                }
+                _ => return check_response(response),
+            }
+        }
+    }
+}
+
+fn check_response(response: Response) -> Result<Response, Error> {
+    if response.is_success() {
+        Ok(response)
+    } else {
+        Err(Error::from_response(&response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rate_limit::tests::{response, time, MockClock};
+    use super::stub::StubTransport;
+    use super::*;
+    use crate::content::parse::parse_content;
+    use crate::entities::Visibility;
+    use std::time::Duration;
+
+    const STATUS: &str = include_str!("entities/tests/status.json");
+
+    fn client(transport: &StubTransport) -> Client<&StubTransport> {
+        let base = Url::parse("https://mastodon.social").unwrap();
+        Client::new(transport, base).with_token("token".to_string())
+    }
+
+    #[test]
+    fn test_status() {
+        // 109818097593839444 on mastodon.social
+        let transport = StubTransport::default();
+        transport.push(200, STATUS);
+        let status = client(&transport).status("109818097593839444").unwrap();
+        assert_eq!(
+            status.content.parsed(),
+            &parse_content(include_str!("content/tests/real_with_mentions.html"))[..]
+        );
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].method, Method::Get);
+        assert_eq!(
+            requests[0].url.as_str(),
+            "https://mastodon.social/api/v1/statuses/109818097593839444"
+        );
+        assert_eq!(requests[0].header("authorization"), Some("Bearer token"));
+    }
+
+    #[test]
+    fn test_timelines() {
+        let transport = StubTransport::default();
+        transport.push(200, &format!("[{}]", STATUS));
+        transport.push(200, "[]");
+        let client = client(&transport);
+        assert_eq!(client.public_timeline(true).unwrap().len(), 1);
+        assert!(client.hashtag_timeline("rust lang").unwrap().is_empty());
+
+        let urls = transport
+            .requests()
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                "https://mastodon.social/api/v1/timelines/public?local=true",
+                "https://mastodon.social/api/v1/timelines/tag/rust%20lang"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_actions() {
+        let transport = StubTransport::default();
+        transport.push(200, STATUS);
+        client(&transport).favourite("109818097593839444").unwrap();
+        let requests = transport.requests();
+        assert_eq!(requests[0].method, Method::Post);
+        assert_eq!(
+            requests[0].url.path(),
+            "/api/v1/statuses/109818097593839444/favourite"
+        );
+    }
+
+    #[test]
+    fn test_paths() {
+        let transport = StubTransport::default();
+        transport.push(200, STATUS);
+        transport.push(200, STATUS);
+        let client = client(&transport);
+        client.delete_status("1/../../accounts/2?x#y").unwrap();
+        let base = Url::parse("https://example.com/mastodon").unwrap();
+        let client = Client::new(&transport, base);
+        client.status("1").unwrap();
+
+        let requests = transport.requests();
+        assert_eq!(requests[0].method, Method::Delete);
+        assert_eq!(
+            requests[0].url.as_str(),
+            "https://mastodon.social/api/v1/statuses/1%2F..%2F..%2Faccounts%2F2%3Fx%23y"
+        );
+        assert_eq!(
+            requests[1].url.as_str(),
+            "https://example.com/mastodon/api/v1/statuses/1"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let transport = StubTransport::default();
+        transport.push(404, r#"{"error": "Record not found"}"#);
+        transport.push(502, "<html>Bad gateway</html>");
+        transport.push(200, "{}");
+        let client = client(&transport);
+        match client.account("1") {
+            Err(Error::NotFound { message }) => {
+                assert_eq!(message.as_deref(), Some("Record not found"));
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        match client.account("1") {
+            Err(Error::Http { status, message }) => {
+                assert_eq!(status, 502);
+                assert_eq!(message, None);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(matches!(client.account("1"), Err(Error::Decode(_))));
+        assert!(matches!(client.account("1"), Err(Error::Transport(_))));
+    }
+
+    #[test]
+    fn test_rate_limit_retry() {
+        let (clock, sleeps) = MockClock::new(time(1_675_938_000));
+        let transport = StubTransport::default();
+        let mut first = response(200, 1, "2023-02-09T10:21:00Z");
+        first.body = STATUS.as_bytes().to_vec();
+        transport.push_response(first);
+        transport.push_response(response(429, 0, "2023-02-09T10:21:00Z"));
+        transport.push(503, "");
+        transport.push(200, STATUS);
+        let client = client(&transport).with_rate_limiter(RateLimiter::default().with_clock(clock));
+
+        client.status("109818097593839444").unwrap();
+        let status = client.status("109818097593839444").unwrap();
+        assert_eq!(status.id, "109818097593839444");
+        assert_eq!(transport.requests().len(), 4);
+        // Delayed before the second request, after the 429, then after the 503
+        assert_eq!(
+            *sleeps.lock().unwrap(),
+            vec![
+                Duration::from_secs(30),
+                Duration::from_secs(30),
+                Duration::from_secs(2)
+            ]
+        );
+        let limit = client.rate_limiter().limit(Bucket::General).unwrap();
+        assert_eq!(limit.remaining, 0);
+    }
+
+    #[test]
+    fn test_post_status() {
+        let (clock, _) = MockClock::new(time(0));
+        let transport = StubTransport::default();
+        transport.push(503, "");
+        transport.push(200, STATUS);
+        transport.push(
+            200,
+            r#"{"id": "1", "scheduled_at": "2023-02-09T10:25:00.000Z"}"#,
+        );
+        let client = client(&transport).with_rate_limiter(RateLimiter::default().with_clock(clock));
+        let limits = InstanceLimits::default();
+
+        let status = StatusBuilder::new("Hello #rust")
+            .visibility(Visibility::Unlisted)
+            .language("en");
+        let posted = client.post_status(&status, &limits).unwrap();
+        assert!(matches!(posted, Posted::Status(_)));
+        let requests = transport.requests();
+        assert_eq!(requests[0].url.path(), "/api/v1/statuses");
+        // The request was retried with the same key
+        assert_eq!(requests[0].header("Idempotency-Key"), Some(status.key()));
+        assert_eq!(requests[1].header("Idempotency-Key"), Some(status.key()));
+        let body: serde_json::Value =
+            serde_json::from_slice(requests[1].body.as_ref().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"status": "Hello #rust", "visibility": "unlisted", "language": "en"})
+        );
+
+        let status = StatusBuilder::new("Later").scheduled_at(time(1_675_938_300));
+        match client.post_status(&status, &limits).unwrap() {
+            Posted::Scheduled(scheduled) => assert_eq!(scheduled.id, "1"),
+            posted => panic!("unexpected result {:?}", posted),
+        }
+        let body: serde_json::Value = serde_json::from_slice(
+            transport.requests()[2]
+                .body
+                .as_ref()
+                .unwrap()
+                .as_bytes()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(body["scheduled_at"], "2023-02-09T10:25:00Z");
+
+        // Invalid statuses are not sent
+        let status = StatusBuilder::new("");
+        assert!(matches!(
+            client.post_status(&status, &limits),
+            Err(Error::Validation { .. })
+        ));
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_instance_limits() {
+        let transport = StubTransport::default();
+        transport.push(
+            200,
+            r#"{
+                "domain": "mastodon.social",
+                "configuration": {
+                    "statuses": {"max_characters": 500, "max_media_attachments": 4},
+                    "polls": {
+                        "max_options": 4,
+                        "max_characters_per_option": 50,
+                        "min_expiration": 300,
+                        "max_expiration": 2629746
+                    },
+                    "media_attachments": {
+                        "image_size_limit": 16777216,
+                        "image_matrix_limit": 33177600
+                    }
+                }
+            }"#,
+        );
+        let limits = client(&transport).instance_limits().unwrap();
+        assert_eq!(limits, InstanceLimits::default());
+        assert_eq!(transport.requests()[0].url.path(), "/api/v2/instance");
+
+        // Missing limits fall back to the defaults
+        transport.push(
+            200,
+            r#"{
+                "domain": "mastodon.social",
+                "configuration": {"statuses": {"max_characters": 1000}}
+            }"#,
+        );
+        let limits = client(&transport).instance_limits().unwrap();
+        let expected = InstanceLimits {
+            max_characters: 1000,
+            ..InstanceLimits::default()
+        };
+        assert_eq!(limits, expected);
+    }
+
+    #[test]
+    fn test_upload_media() {
+        let media = |url: &str| {
+            format!(
+                r#"{{"id": "22348641", "type": "video", "url": {}, "preview_url": null}}"#,
+                url
+            )
+        };
+        let (clock, sleeps) = MockClock::new(time(0));
+        let transport = StubTransport::default();
+        transport.push(202, &media("null"));
+        transport.push(206, &media("null"));
+        transport.push(200, &media(r#""https://files.mastodon.social/video.mp4""#));
+        transport.push(202, &media("null"));
+        transport.push(206, &media("null"));
+        let client = client(&transport).with_rate_limiter(RateLimiter::default().with_clock(clock));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = events.clone();
+        let upload = MediaUpload::new(&b"video"[..], "video.mp4", "video/mp4").description("A cat");
+        let media = client
+            .upload_media(upload, move |progress| {
+                recorder.lock().unwrap().push(progress)
+            })
+            .unwrap();
+        assert_eq!(
+            media.url.as_deref(),
+            Some("https://files.mastodon.social/video.mp4")
+        );
+        assert_eq!(
+            events.lock().unwrap()[..],
+            [
+                Progress::Uploading {
+                    sent: 0,
+                    total: None
+                },
+                Progress::Uploading {
+                    sent: 5,
+                    total: None
+                },
+                Progress::Processing { attempt: 0 },
+                Progress::Processing { attempt: 1 },
+                Progress::Done
+            ]
+        );
+        assert_eq!(
+            *sleeps.lock().unwrap(),
+            vec![Duration::from_secs(1), Duration::from_secs(2)]
+        );
+        let requests = transport.requests();
+        assert_eq!(requests[0].url.path(), "/api/v2/media");
+        assert!(requests[0]
+            .header("Content-Type")
+            .unwrap()
+            .starts_with("multipart/form-data; boundary=crabodon-"));
+        assert_eq!(requests[2].url.path(), "/api/v1/media/22348641");
+
+        let upload = MediaUpload::new(&b"video"[..], "video.mp4", "video/mp4").polling(
+            media::Polling::new(Duration::from_secs(1), Duration::from_secs(1), 1),
+        );
+        assert!(matches!(
+            client.upload_media(upload, |_| {}),
+            Err(Error::Timeout)
+        ));
+
+        // Streamed uploads are not retried
+        transport.push(503, "");
+        let upload = MediaUpload::new(&b"video"[..], "video.mp4", "video/mp4");
+        assert!(matches!(
+            client.upload_media(upload, |_| {}),
+            Err(Error::Http { status: 503, .. })
+        ));
+        assert_eq!(transport.requests().len(), 6);
+    }
+}
@@ -0,0 +1,313 @@
+//! Upload media
+//!
+//! Media are uploaded before being attached to a status. [`MediaUpload`] describes
+//! the file to upload, with its description and focal point, and is uploaded with
+//! [`Client::upload_media`](super::Client::upload_media).
+//!
+//! Mastodon processes large media, like videos, after the upload. While the media
+//! is being processed, its URL is not set, and the client polls it with a
+//! backoff, according to [`Polling`]. The progress of the upload and of the
+//! processing is reported with [`Progress`].
+//!
+//! The file is streamed to the [`Transport`](super::Transport) as a
+//! [`Body::Reader`], and progress is reported as the transport reads it.
+//!
+//! With the `image` feature, images can be prepared locally before the upload,
+//! see the `preprocess` module.
+
+#[cfg(feature = "image")]
+pub mod preprocess;
+
+use super::transport::Body;
+use crate::backoff::backoff;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Polling policy
+///
+/// The delay between polls doubles after each poll, up to a maximum.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Polling {
+    /// Delay before the first poll
+    pub initial_delay: Duration,
+    /// Maximum delay between polls
+    pub max_delay: Duration,
+    /// Maximum number of polls
+    pub max_attempts: u32,
+}
+
+impl Polling {
+    /// Create a polling policy
+    pub fn new(initial_delay: Duration, max_delay: Duration, max_attempts: u32) -> Self {
+        Polling {
+            initial_delay,
+            max_delay,
+            max_attempts,
+        }
+    }
+
+    /// Delay before a poll
+    ///
+    /// `attempt` is the number of previous polls.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        backoff(self.initial_delay, self.max_delay, attempt)
+    }
+}
+
+impl Default for Polling {
+    fn default() -> Self {
+        Polling::new(Duration::from_secs(1), Duration::from_secs(10), 60)
+    }
+}
+
+/// Progress of an upload
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Progress {
+    /// The file is being read
+    Uploading {
+        /// Number of bytes read
+        sent: u64,
+        /// Size of the file, if known
+        total: Option<u64>,
+    },
+    /// The file was uploaded, and is being processed
+    Processing {
+        /// Number of polls
+        attempt: u32,
+    },
+    /// The media is ready
+    Done,
+}
+
+/// A media to upload
+pub struct MediaUpload<R> {
+    reader: R,
+    file_name: String,
+    mime_type: String,
+    size: Option<u64>,
+    description: Option<String>,
+    focus: Option<(f32, f32)>,
+    polling: Polling,
+}
+
+impl<R> MediaUpload<R>
+where
+    R: Read,
+{
+    /// Create a media upload
+    ///
+    /// `mime_type` is the type of the file, like `image/jpeg`.
+    pub fn new(reader: R, file_name: &str, mime_type: &str) -> Self {
+        MediaUpload {
+            reader,
+            file_name: file_name.to_string(),
+            mime_type: mime_type.to_string(),
+            size: None,
+            description: None,
+            focus: None,
+            polling: Polling::default(),
+        }
+    }
+
+    /// Set the size of the file, in bytes
+    ///
+    /// The size is used to report progress.
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Set the alternative text
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Set the focal point
+    ///
+    /// Coordinates range from -1.0 to 1.0, from left to right, and from bottom
+    /// to top. The focal point is kept visible when the media is cropped.
+    pub fn focus(mut self, x: f32, y: f32) -> Self {
+        self.focus = Some((x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0)));
+        self
+    }
+
+    /// Set the polling policy
+    pub fn polling(mut self, polling: Polling) -> Self {
+        self.polling = polling;
+        self
+    }
+
+    pub(crate) fn polling_policy(&self) -> Polling {
+        self.polling
+    }
+
+    /// Encode the upload as a streamed `multipart/form-data` body
+    ///
+    /// Returns the content type, with the boundary, and the body. Progress is
+    /// reported as the file is read from the body.
+    pub(crate) fn into_multipart<F>(self, boundary: &str, progress: Arc<Mutex<F>>) -> (String, Body)
+    where
+        R: Send + 'static,
+        F: FnMut(Progress) + Send + 'static,
+    {
+        let mut head = Vec::new();
+        if let Some(description) = &self.description {
+            push_field(&mut head, boundary, "description", description);
+        }
+        if let Some((x, y)) = self.focus {
+            push_field(&mut head, boundary, "focus", &format!("{:.2},{:.2}", x, y));
+        }
+        head.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+                 Content-Type: {}\r\n\r\n",
+                boundary,
+                escape(&self.file_name),
+                escape(&self.mime_type)
+            )
+            .as_bytes(),
+        );
+        let tail = format!("\r\n--{}--\r\n", boundary).into_bytes();
+
+        report(
+            &progress,
+            Progress::Uploading {
+                sent: 0,
+                total: self.size,
+            },
+        );
+        let file = ProgressReader {
+            reader: self.reader,
+            sent: 0,
+            total: self.size,
+            progress,
+        };
+        let body = Cursor::new(head).chain(file).chain(Cursor::new(tail));
+
+        let content_type = format!("multipart/form-data; boundary={}", boundary);
+        (content_type, Body::Reader(Box::new(body)))
+    }
+}
+
+/// A reader reporting the progress of the upload
+struct ProgressReader<R, F> {
+    reader: R,
+    sent: u64,
+    total: Option<u64>,
+    progress: Arc<Mutex<F>>,
+}
+
+impl<R, F> Read for ProgressReader<R, F>
+where
+    R: Read,
+    F: FnMut(Progress),
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        if read > 0 {
+            self.sent += read as u64;
+            let progress = Progress::Uploading {
+                sent: self.sent,
+                total: self.total,
+            };
+            report(&self.progress, progress);
+        }
+        Ok(read)
+    }
+}
+
+/// Report progress
+pub(crate) fn report<F>(callback: &Mutex<F>, progress: Progress)
+where
+    F: FnMut(Progress),
+{
+    // A panic in the callback does not leave any state to recover
+    let mut callback = match callback.lock() {
+        Ok(callback) => callback,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    (*callback)(progress);
+}
+
+fn push_field(body: &mut Vec<u8>, boundary: &str, name: &str, value: &str) {
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            boundary, name, value
+        )
+        .as_bytes(),
+    );
+}
+
+/// Escape a header parameter value
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = events.clone();
+        let progress = Arc::new(Mutex::new(move |progress| {
+            recorder.lock().unwrap().push(progress)
+        }));
+        let upload = MediaUpload::new(&b"GIF89a"[..], "cat \"1\".gif", "image/gif")
+            .size(6)
+            .description("A cat")
+            .focus(0.5, -2.0);
+        let (content_type, body) = upload.into_multipart("boundary", progress);
+        assert_eq!(content_type, "multipart/form-data; boundary=boundary");
+        assert_eq!(body.as_bytes(), None);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![Progress::Uploading {
+                sent: 0,
+                total: Some(6)
+            }]
+        );
+
+        let mut reader = match body {
+            Body::Reader(reader) => reader,
+            body => panic!("unexpected body {:?}", body),
+        };
+        let mut body = String::new();
+        reader.read_to_string(&mut body).unwrap();
+        let expected = concat!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"description\"\r\n\r\n",
+            "A cat\r\n",
+            "--boundary\r\nContent-Disposition: form-data; name=\"focus\"\r\n\r\n",
+            "0.50,-1.00\r\n",
+            "--boundary\r\nContent-Disposition: form-data; name=\"file\"; ",
+            "filename=\"cat %221%22.gif\"\r\nContent-Type: image/gif\r\n\r\n",
+            "GIF89a\r\n--boundary--\r\n"
+        );
+        assert_eq!(body, expected);
+        assert_eq!(
+            events.lock().unwrap()[1..],
+            [Progress::Uploading {
+                sent: 6,
+                total: Some(6)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_polling_delay() {
+        let polling = Polling::default();
+        let delays = (0..6)
+            .map(|i| polling.delay(i).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+    }
+}
@@ -0,0 +1,314 @@
+//! Preprocess images
+//!
+//! Photos taken with phones are large, and their metadata often include the
+//! location they were taken at. [`prepare_image`] processes an image locally
+//! before it is uploaded:
+//!
+//! - The image is rotated according to its EXIF orientation.
+//! - The image is decoded and encoded again, which drops all metadata.
+//! - The image is downscaled to fit the limits of the instance.
+//!
+//! This module requires the `image` feature. JPEG and PNG images are encoded again
+//! in their format. Other images, like animated GIF or WebP images, would lose their
+//! animation or grow when encoded again, and are rejected: they should be uploaded
+//! unchanged.
+
+use super::MediaUpload;
+use crate::client::post::InstanceLimits;
+use crate::{Error, FieldError};
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use std::io::Cursor;
+
+/// Quality of encoded JPEG images
+const JPEG_QUALITY: u8 = 90;
+
+/// Maximum number of times an image is downscaled to fit the size limit
+const MAX_DOWNSCALES: u32 = 8;
+
+/// A prepared image
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct PreparedImage {
+    /// Encoded image
+    pub data: Vec<u8>,
+    /// MIME type of the image
+    pub mime_type: &'static str,
+    /// Width of the image
+    pub width: u32,
+    /// Height of the image
+    pub height: u32,
+}
+
+/// Prepare an image for upload
+///
+/// The image is oriented, stripped of its metadata, and downscaled to the image
+/// limits of the instance. A [`Error::Validation`] error is returned if the image
+/// is not a JPEG or PNG image, or if it still exceeds the size limit after being
+/// downscaled several times.
+pub fn prepare_image(data: &[u8], limits: &InstanceLimits) -> Result<PreparedImage, Error> {
+    let format = image::guess_format(data)?;
+    let (output, mime_type) = match format {
+        ImageFormat::Jpeg => (ImageOutputFormat::Jpeg(JPEG_QUALITY), "image/jpeg"),
+        ImageFormat::Png => (ImageOutputFormat::Png, "image/png"),
+        _ => {
+            return Err(invalid_file(
+                "Unsupported image format",
+                "ERR_UNSUPPORTED",
+                "is not a JPEG or PNG image",
+            ))
+        }
+    };
+    let mut image = image::load_from_memory_with_format(data, format)?;
+    if format == ImageFormat::Jpeg {
+        if let Some(orientation) = jpeg_orientation(data) {
+            image = orient(image, orientation);
+        }
+    }
+    if format == ImageFormat::Jpeg {
+        image = DynamicImage::ImageRgb8(image.to_rgb8());
+    }
+
+    let pixels = u64::from(image.width()) * u64::from(image.height());
+    if pixels > limits.image_matrix_limit {
+        let scale = (limits.image_matrix_limit as f64 / pixels as f64).sqrt();
+        image = downscale(&image, scale);
+    }
+    let mut data = encode(&image, &output)?;
+    let mut attempts = 0;
+    while data.len() as u64 > limits.image_size_limit && attempts < MAX_DOWNSCALES {
+        image = downscale(&image, 0.8);
+        data = encode(&image, &output)?;
+        attempts += 1;
+    }
+    if data.len() as u64 > limits.image_size_limit {
+        return Err(invalid_file(
+            "Image too large",
+            "ERR_TOO_LARGE",
+            &format!(
+                "is too large after downscaling ({} bytes, maximum is {})",
+                data.len(),
+                limits.image_size_limit
+            ),
+        ));
+    }
+
+    Ok(PreparedImage {
+        data,
+        mime_type,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+impl MediaUpload<Cursor<Vec<u8>>> {
+    /// Create a media upload from an image
+    ///
+    /// The image is prepared with [`prepare_image`].
+    pub fn from_image(
+        data: &[u8],
+        file_name: &str,
+        limits: &InstanceLimits,
+    ) -> Result<Self, Error> {
+        let image = prepare_image(data, limits)?;
+        let size = image.data.len() as u64;
+        Ok(MediaUpload::new(Cursor::new(image.data), file_name, image.mime_type).size(size))
+    }
+}
+
+fn invalid_file(message: &str, error: &str, description: &str) -> Error {
+    Error::Validation {
+        message: Some(message.to_string()),
+        details: vec![FieldError {
+            field: "file".to_string(),
+            error: error.to_string(),
+            description: description.to_string(),
+        }],
+    }
+}
+
+fn downscale(image: &DynamicImage, scale: f64) -> DynamicImage {
+    let width = ((f64::from(image.width()) * scale) as u32).max(1);
+    let height = ((f64::from(image.height()) * scale) as u32).max(1);
+    image.resize_exact(width, height, FilterType::CatmullRom)
+}
+
+fn encode(image: &DynamicImage, format: &ImageOutputFormat) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), format.clone())?;
+    Ok(data)
+}
+
+/// Apply an EXIF orientation
+fn orient(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Read the EXIF orientation of a JPEG image
+fn jpeg_orientation(data: &[u8]) -> Option<u16> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut position = 2;
+    while position + 4 <= data.len() {
+        if data[position] != 0xFF {
+            return None;
+        }
+        let marker = data[position + 1];
+        // Metadata come before the start of the scan
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let length = usize::from(u16::from_be_bytes([data[position + 2], data[position + 3]]));
+        let segment = data.get(position + 4..position + 2 + length)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return tiff_orientation(&segment[6..]);
+        }
+        position += 2 + length;
+    }
+    None
+}
+
+/// Read the orientation tag of the first IFD of a TIFF header
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 2)?;
+        let bytes = [bytes[0], bytes[1]];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let read_u32 = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let ifd = read_u32(4)? as usize;
+    let count = usize::from(read_u16(ifd)?);
+    (0..count)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|entry| read_u16(*entry) == Some(0x0112))
+        .and_then(|entry| read_u16(entry + 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// Encode a JPEG image, with an EXIF segment holding an orientation
+    fn jpeg(width: u32, height: u32, orientation: u16) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 128]));
+        let jpeg = encode(
+            &DynamicImage::ImageRgb8(image),
+            &ImageOutputFormat::Jpeg(90),
+        )
+        .unwrap();
+
+        // Little-endian TIFF header, with a single IFD entry
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend_from_slice(&tiff);
+        segment.extend_from_slice(b"GPS 48.8584 N 2.2945 E");
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&(segment.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(&segment);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    #[test]
+    fn test_orientation() {
+        assert_eq!(jpeg_orientation(&jpeg(4, 2, 6)), Some(6));
+        assert_eq!(jpeg_orientation(b"\xFF\xD8\xFF\xD9"), None);
+        assert_eq!(jpeg_orientation(b"GIF89a"), None);
+        assert_eq!(
+            tiff_orientation(b"MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x03\0\0"),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_prepare_image() {
+        let data = jpeg(40, 20, 6);
+        let image = prepare_image(&data, &InstanceLimits::default()).unwrap();
+        assert_eq!(image.mime_type, "image/jpeg");
+        assert_eq!((image.width, image.height), (20, 40));
+        assert_eq!(jpeg_orientation(&image.data), None);
+        let metadata = b"GPS";
+        assert!(!image.data.windows(3).any(|window| window == metadata));
+    }
+
+    #[test]
+    fn test_downscale() {
+        let limits = InstanceLimits {
+            image_matrix_limit: 200,
+            ..InstanceLimits::default()
+        };
+        let image = prepare_image(&jpeg(40, 20, 1), &limits).unwrap();
+        assert_eq!((image.width, image.height), (20, 10));
+
+        let limits = InstanceLimits {
+            image_size_limit: 1000,
+            ..InstanceLimits::default()
+        };
+        let image = prepare_image(&jpeg(200, 200, 1), &limits).unwrap();
+        assert!(image.data.len() <= 1000);
+        assert!(image.width < 200);
+
+        let limits = InstanceLimits {
+            image_size_limit: 10,
+            ..InstanceLimits::default()
+        };
+        assert!(matches!(
+            prepare_image(&jpeg(200, 200, 1), &limits),
+            Err(Error::Validation { details, .. }) if details[0].error == "ERR_TOO_LARGE"
+        ));
+
+        let limits = InstanceLimits::default();
+        let upload = MediaUpload::from_image(&jpeg(4, 4, 1), "photo.jpg", &limits).unwrap();
+        assert_eq!(upload.mime_type, "image/jpeg");
+    }
+
+    #[test]
+    fn test_formats() {
+        let image = RgbImage::from_fn(4, 2, |x, y| Rgb([x as u8, y as u8, 128]));
+        let png = encode(&DynamicImage::ImageRgb8(image), &ImageOutputFormat::Png).unwrap();
+        let image = prepare_image(&png, &InstanceLimits::default()).unwrap();
+        assert_eq!(image.mime_type, "image/png");
+        assert_eq!((image.width, image.height), (4, 2));
+
+        // Animated GIF and WebP images are not encoded again
+        for data in &[&b"GIF89a\x04\0\x02\0"[..], &b"RIFF\0\0\0\0WEBPVP8 "[..]] {
+            assert!(matches!(
+                prepare_image(data, &InstanceLimits::default()),
+                Err(Error::Validation { details, .. }) if details[0].error == "ERR_UNSUPPORTED"
+            ));
+        }
+    }
+}
@@ -0,0 +1,532 @@
+//! Paginate list endpoints
+//!
+//! Mastodon paginates lists, like timelines, with a `Link` header, that contains
+//! the URLs of the next page, with older items, and of the previous page, with
+//! newer items. This module parses this header into [`Cursor`]s, and offers
+//! [`Pages`], an iterator over the pages of a list endpoint.
+//!
+//! Some endpoints, like trends or the profile directory, are paginated with an
+//! `offset` parameter instead. [`Pages::with_offset`] supports them. Search results
+//! are also paginated with an offset, one [`SearchKind`] at a time, see
+//! [`Client::search_pages`].
+
+use super::transport::Transport;
+use super::{Client, Error};
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+use url::Url;
+
+/// Query parameters used as cursors
+const CURSOR_PARAMS: &[&str] = &["max_id", "min_id", "since_id", "offset"];
+
+/// A position in a list
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Cursor {
+    /// Return items older than this identifier
+    pub max_id: Option<String>,
+    /// Return items immediately newer than this identifier
+    pub min_id: Option<String>,
+    /// Return items newer than this identifier
+    pub since_id: Option<String>,
+    /// Skip this number of items
+    pub offset: Option<u64>,
+}
+
+impl Cursor {
+    /// Create a cursor from the query of a URL
+    ///
+    /// Returns `None` if the URL does not contain any cursor parameter.
+    pub fn from_url(url: &Url) -> Option<Self> {
+        let mut cursor = Cursor::default();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "max_id" => cursor.max_id = Some(value.into_owned()),
+                "min_id" => cursor.min_id = Some(value.into_owned()),
+                "since_id" => cursor.since_id = Some(value.into_owned()),
+                "offset" => cursor.offset = value.parse().ok(),
+                _ => {}
+            }
+        }
+        if cursor == Cursor::default() {
+            None
+        } else {
+            Some(cursor)
+        }
+    }
+
+    /// Apply the cursor to a URL
+    ///
+    /// Cursor parameters already present in the URL are replaced.
+    pub fn apply(&self, url: &mut Url) {
+        let pairs = url
+            .query_pairs()
+            .filter(|(key, _)| !CURSOR_PARAMS.contains(&key.as_ref()))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect::<Vec<_>>();
+        let mut query = url.query_pairs_mut();
+        query.clear().extend_pairs(pairs);
+        if let Some(max_id) = &self.max_id {
+            query.append_pair("max_id", max_id);
+        }
+        if let Some(min_id) = &self.min_id {
+            query.append_pair("min_id", min_id);
+        }
+        if let Some(since_id) = &self.since_id {
+            query.append_pair("since_id", since_id);
+        }
+        if let Some(offset) = self.offset {
+            query.append_pair("offset", &offset.to_string());
+        }
+        drop(query);
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+    }
+}
+
+/// Links of a `Link` header
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Links {
+    /// Cursor of the next page, with older items
+    pub next: Option<Cursor>,
+    /// Cursor of the previous page, with newer items
+    pub prev: Option<Cursor>,
+}
+
+/// Parse a `Link` header
+///
+/// Links that are not valid URLs, or that do not contain any
+/// cursor parameter, are ignored.
+pub fn parse_link_header(value: &str) -> Links {
+    let mut links = Links::default();
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let url = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+        let params_end = rest.find('<').unwrap_or(rest.len());
+        let params = rest[..params_end].trim().trim_end_matches(',');
+
+        let cursor = Url::parse(url).ok().and_then(|url| Cursor::from_url(&url));
+        for param in params.split(';') {
+            let mut parts = param.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts.next().unwrap_or("").trim().trim_matches('"');
+            if name.eq_ignore_ascii_case("rel") {
+                for rel in value.split_whitespace() {
+                    match rel {
+                        "next" => links.next = cursor.clone(),
+                        "prev" => links.prev = cursor.clone(),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    links
+}
+
+/// A page of a list
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Page<R> {
+    /// Items of the page
+    pub items: Vec<R>,
+    /// Cursor of the next page, with older items
+    pub next: Option<Cursor>,
+    /// Cursor of the previous page, with newer items
+    pub prev: Option<Cursor>,
+}
+
+/// A kind of search result
+///
+/// Search returns accounts, statuses and hashtags together. Each kind is
+/// paginated separately.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum SearchKind {
+    /// Accounts, as [`Account`](crate::entities::Account)s
+    Accounts,
+    /// Statuses, as [`Status`](crate::entities::Status)es
+    Statuses,
+    /// Hashtags, as [`Tag`](crate::entities::Tag)s
+    Hashtags,
+}
+
+impl SearchKind {
+    /// Value of the `type` parameter
+    pub(crate) fn param(self) -> &'static str {
+        match self {
+            SearchKind::Accounts => "accounts",
+            SearchKind::Statuses => "statuses",
+            SearchKind::Hashtags => "hashtags",
+        }
+    }
+
+    /// Field of the response containing the results
+    pub(crate) fn field(self) -> &'static str {
+        self.param()
+    }
+}
+
+/// A list specifying directions of pagination
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Direction {
+    Older,
+    Newer,
+}
+
+/// An iterator over the pages of a list endpoint
+///
+/// Pages are fetched lazily. By default, pages with older items are fetched,
+/// until a page is empty or has no next page. Iteration also stops after an
+/// error.
+pub struct Pages<'a, T, R> {
+    client: &'a Client<T>,
+    url: Url,
+    cursor: Option<Cursor>,
+    direction: Direction,
+    use_offset: bool,
+    field: Option<&'static str>,
+    max_pages: Option<usize>,
+    max_items: Option<usize>,
+    page_count: usize,
+    item_count: usize,
+    done: bool,
+    item_type: PhantomData<R>,
+}
+
+impl<'a, T, R> Pages<'a, T, R>
+where
+    T: Transport,
+    R: DeserializeOwned,
+{
+    pub(crate) fn new(client: &'a Client<T>, url: Url) -> Self {
+        Pages {
+            client,
+            url,
+            cursor: None,
+            direction: Direction::Older,
+            use_offset: false,
+            field: None,
+            max_pages: None,
+            max_items: None,
+            page_count: 0,
+            item_count: 0,
+            done: false,
+            item_type: PhantomData,
+        }
+    }
+
+    /// Read the items from a field of the response, instead of the response
+    pub(crate) fn in_field(mut self, field: &'static str) -> Self {
+        self.field = Some(field);
+        self
+    }
+
+    /// Start at a cursor
+    ///
+    /// By default, iteration starts at the most recent items.
+    pub fn starting_at(mut self, cursor: Cursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    /// Fetch pages with newer items
+    ///
+    /// Pages are fetched by following the previous page links.
+    pub fn newer(mut self) -> Self {
+        self.direction = Direction::Newer;
+        self
+    }
+
+    /// Paginate with an offset
+    ///
+    /// When a response has no `Link` header, the next page is fetched with
+    /// an `offset` parameter, that skips the items already returned. This is
+    /// how trends and the profile directory are paginated.
+    pub fn with_offset(mut self) -> Self {
+        self.use_offset = true;
+        self
+    }
+
+    /// Set the number of items per page
+    ///
+    /// This is the `limit` parameter of Mastodon. The server uses a
+    /// default, usually 20, when it is not set.
+    pub fn page_size(mut self, size: u32) -> Self {
+        self.url
+            .query_pairs_mut()
+            .append_pair("limit", &size.to_string());
+        self
+    }
+
+    /// Stop after a number of pages
+    pub fn max_pages(mut self, count: usize) -> Self {
+        self.max_pages = Some(count);
+        self
+    }
+
+    /// Stop after a number of items
+    ///
+    /// The last page is truncated to this number of items.
+    pub fn max_items(mut self, count: usize) -> Self {
+        self.max_items = Some(count);
+        self
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.done
+            || self
+                .max_pages
+                .map(|max| self.page_count >= max)
+                .unwrap_or(false)
+            || self
+                .max_items
+                .map(|max| self.item_count >= max)
+                .unwrap_or(false)
+    }
+
+    fn fetch(&mut self) -> Result<Page<R>, Error> {
+        let mut url = self.url.clone();
+        if let Some(cursor) = &self.cursor {
+            cursor.apply(&mut url);
+        }
+        let mut page = self.client.page::<R>(url, self.field)?;
+
+        if self.use_offset && page.next.is_none() && !page.items.is_empty() {
+            let offset = self.cursor.as_ref().and_then(|cursor| cursor.offset);
+            page.next = Some(Cursor {
+                offset: Some(offset.unwrap_or(0) + page.items.len() as u64),
+                ..Cursor::default()
+            });
+        }
+        if let Some(max) = self.max_items {
+            page.items.truncate(max - self.item_count);
+        }
+        Ok(page)
+    }
+}
+
+impl<'a, T, R> Iterator for Pages<'a, T, R>
+where
+    T: Transport,
+    R: DeserializeOwned,
+{
+    type Item = Result<Page<R>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_exhausted() {
+            return None;
+        }
+
+        let page = match self.fetch() {
+            Ok(page) => page,
+            Err(error) => {
+                self.done = true;
+                return Some(Err(error));
+            }
+        };
+        if page.items.is_empty() {
+            self.done = true;
+            return None;
+        }
+
+        self.page_count += 1;
+        self.item_count += page.items.len();
+        let cursor = match self.direction {
+            Direction::Older => &page.next,
+            Direction::Newer => &page.prev,
+        };
+        match cursor {
+            Some(cursor) => self.cursor = Some(cursor.clone()),
+            None => self.done = true,
+        }
+        Some(Ok(page))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stub::StubTransport;
+    use super::super::transport::Response;
+    use super::*;
+    use crate::entities::{Account, Status, Tag};
+
+    const STATUS: &str = include_str!("../entities/tests/status.json");
+    const SEARCH: &str = include_str!("../entities/tests/search.json");
+
+    fn link_response(body: &str, link: &str) -> Response {
+        let headers = vec![("Link".to_string(), link.to_string())];
+        Response::new(200, headers, body.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_parse_link_header() {
+        let header = concat!(
+            r#"<https://mastodon.social/api/v1/timelines/home?limit=2&max_id=109818097593839444>; rel="next", "#,
+            r#"<https://mastodon.social/api/v1/timelines/home?limit=2&min_id=109818097593839445>; rel="prev""#
+        );
+        let links = parse_link_header(header);
+        assert_eq!(
+            links.next.unwrap().max_id.as_deref(),
+            Some("109818097593839444")
+        );
+        assert_eq!(
+            links.prev.unwrap().min_id.as_deref(),
+            Some("109818097593839445")
+        );
+        assert_eq!(parse_link_header(""), Links::default());
+        assert_eq!(
+            parse_link_header("<not a url>; rel=\"next\""),
+            Links::default()
+        );
+    }
+
+    #[test]
+    fn test_cursor_apply() {
+        let mut url =
+            Url::parse("https://mastodon.social/api/v1/timelines/home?limit=2&max_id=1").unwrap();
+        let cursor = Cursor {
+            min_id: Some("3".to_string()),
+            ..Cursor::default()
+        };
+        cursor.apply(&mut url);
+        assert_eq!(
+            url.as_str(),
+            "https://mastodon.social/api/v1/timelines/home?limit=2&min_id=3"
+        );
+    }
+
+    #[test]
+    fn test_pages() {
+        let transport = StubTransport::default();
+        transport.push_response(link_response(
+            &format!("[{}, {}]", STATUS, STATUS),
+            "<https://mastodon.social/api/v1/timelines/home?max_id=2>; rel=\"next\"",
+        ));
+        transport.push_response(link_response(
+            &format!("[{}]", STATUS),
+            "<https://mastodon.social/api/v1/timelines/home?max_id=1>; rel=\"next\"",
+        ));
+        transport.push(200, "[]");
+
+        let client = Client::new(&transport, Url::parse("https://mastodon.social").unwrap());
+        let pages = client
+            .home_timeline_pages()
+            .unwrap()
+            .page_size(2)
+            .collect::<Result<Vec<Page<Status>>, _>>()
+            .unwrap();
+        assert_eq!(
+            pages
+                .iter()
+                .map(|page| page.items.len())
+                .collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        let urls = transport
+            .requests()
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                "https://mastodon.social/api/v1/timelines/home?limit=2",
+                "https://mastodon.social/api/v1/timelines/home?limit=2&max_id=2",
+                "https://mastodon.social/api/v1/timelines/home?limit=2&max_id=1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let transport = StubTransport::default();
+        for _ in 0..2 {
+            transport.push_response(link_response(
+                &format!("[{}, {}]", STATUS, STATUS),
+                "<https://mastodon.social/api/v1/timelines/home?max_id=2>; rel=\"next\"",
+            ));
+        }
+        let client = Client::new(&transport, Url::parse("https://mastodon.social").unwrap());
+        let items = client
+            .home_timeline_pages()
+            .unwrap()
+            .max_items(3)
+            .map(|page| page.unwrap().items.len())
+            .collect::<Vec<_>>();
+        assert_eq!(items, vec![2, 1]);
+
+        transport.push(200, &format!("[{}]", STATUS));
+        let pages = client.home_timeline_pages().unwrap().max_pages(1).count();
+        assert_eq!(pages, 1);
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_offset_and_errors() {
+        let transport = StubTransport::default();
+        transport.push(200, &format!("[{}, {}]", STATUS, STATUS));
+        transport.push(500, "");
+        let client = Client::new(&transport, Url::parse("https://mastodon.social").unwrap());
+        let mut pages = client
+            .pages::<Status>("api/v1/trends/statuses", &[])
+            .unwrap()
+            .with_offset();
+        assert_eq!(pages.next().unwrap().unwrap().items.len(), 2);
+        assert!(pages.next().unwrap().is_err());
+        assert!(pages.next().is_none());
+        assert_eq!(
+            transport.requests()[1].url.as_str(),
+            "https://mastodon.social/api/v1/trends/statuses?offset=2"
+        );
+    }
+
+    #[test]
+    fn test_search_pages() {
+        let transport = StubTransport::default();
+        transport.push(200, SEARCH);
+        transport.push(200, r#"{"accounts": [], "statuses": [], "hashtags": []}"#);
+        transport.push(200, SEARCH);
+        let client = Client::new(&transport, Url::parse("https://mastodon.social").unwrap());
+
+        let pages = client
+            .search_pages::<Tag>("rust", SearchKind::Hashtags)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        let names = pages[0]
+            .items
+            .iter()
+            .map(|tag| tag.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["rust", "rustlang"]);
+
+        let mut pages = client
+            .search_pages::<Account>("rust", SearchKind::Accounts)
+            .unwrap();
+        let page = pages.next().unwrap().unwrap();
+        assert_eq!(page.items[0].acct, "SfietKonstantin");
+
+        let urls = transport
+            .requests()
+            .into_iter()
+            .map(|request| request.url.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                "https://mastodon.social/api/v2/search?q=rust&type=hashtags",
+                "https://mastodon.social/api/v2/search?q=rust&type=hashtags&offset=2",
+                "https://mastodon.social/api/v2/search?q=rust&type=accounts",
+            ]
+        );
+    }
+}
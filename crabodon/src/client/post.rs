@@ -0,0 +1,535 @@
+//! Post statuses
+//!
+//! [`StatusBuilder`] builds a status to post with
+//! [`Client::post_status`](super::Client::post_status). Before sending it, the
+//! status is checked against the [`InstanceLimits`], so that errors can be shown
+//! to the user without a round trip.
+//!
+//! Each builder has an idempotency key, sent with the `Idempotency-Key` header.
+//! Mastodon does not post a status twice with the same key, so retrying a request
+//! that timed out, or posting the same builder again, is safe. Setting the content
+//! of the builder generates a new key, so that a different status is posted.
+
+use super::timestamp::format_timestamp;
+use crate::compose::count::count_characters;
+use crate::entities::{ScheduledStatus, Status, Visibility};
+use crate::{Error, FieldError};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Limits of an instance
+///
+/// Defaults are the limits of a default Mastodon instance. Actual limits can be
+/// fetched with [`Client::instance_limits`](super::Client::instance_limits).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct InstanceLimits {
+    /// Maximum number of characters of a status
+    pub max_characters: usize,
+    /// Maximum number of media attachments of a status
+    pub max_media_attachments: usize,
+    /// Maximum number of options of a poll
+    pub max_poll_options: usize,
+    /// Maximum number of characters of a poll option
+    pub max_poll_option_characters: usize,
+    /// Minimum duration of a poll
+    pub min_poll_expiration: Duration,
+    /// Maximum duration of a poll
+    pub max_poll_expiration: Duration,
+    /// Maximum size of an image, in bytes
+    pub image_size_limit: u64,
+    /// Maximum number of pixels of an image
+    pub image_matrix_limit: u64,
+}
+
+impl Default for InstanceLimits {
+    fn default() -> Self {
+        InstanceLimits {
+            max_characters: 500,
+            max_media_attachments: 4,
+            max_poll_options: 4,
+            max_poll_option_characters: 50,
+            min_poll_expiration: Duration::from_secs(300),
+            max_poll_expiration: Duration::from_secs(2_629_746),
+            image_size_limit: 16_777_216,
+            image_matrix_limit: 33_177_600,
+        }
+    }
+}
+
+// Instances may omit parts of the configuration, like older versions of
+// Mastodon, and missing limits fall back to the defaults
+#[derive(Deserialize)]
+pub(crate) struct Instance {
+    #[serde(default)]
+    configuration: Configuration,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Configuration {
+    statuses: StatusesConfiguration,
+    polls: PollsConfiguration,
+    media_attachments: MediaConfiguration,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct StatusesConfiguration {
+    max_characters: usize,
+    max_media_attachments: usize,
+}
+
+impl Default for StatusesConfiguration {
+    fn default() -> Self {
+        let limits = InstanceLimits::default();
+        StatusesConfiguration {
+            max_characters: limits.max_characters,
+            max_media_attachments: limits.max_media_attachments,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct PollsConfiguration {
+    max_options: usize,
+    max_characters_per_option: usize,
+    min_expiration: u64,
+    max_expiration: u64,
+}
+
+impl Default for PollsConfiguration {
+    fn default() -> Self {
+        let limits = InstanceLimits::default();
+        PollsConfiguration {
+            max_options: limits.max_poll_options,
+            max_characters_per_option: limits.max_poll_option_characters,
+            min_expiration: limits.min_poll_expiration.as_secs(),
+            max_expiration: limits.max_poll_expiration.as_secs(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct MediaConfiguration {
+    image_size_limit: u64,
+    image_matrix_limit: u64,
+}
+
+impl Default for MediaConfiguration {
+    fn default() -> Self {
+        let limits = InstanceLimits::default();
+        MediaConfiguration {
+            image_size_limit: limits.image_size_limit,
+            image_matrix_limit: limits.image_matrix_limit,
+        }
+    }
+}
+
+impl From<Instance> for InstanceLimits {
+    fn from(instance: Instance) -> Self {
+        let Configuration {
+            statuses,
+            polls,
+            media_attachments,
+        } = instance.configuration;
+        InstanceLimits {
+            max_characters: statuses.max_characters,
+            max_media_attachments: statuses.max_media_attachments,
+            max_poll_options: polls.max_options,
+            max_poll_option_characters: polls.max_characters_per_option,
+            min_poll_expiration: Duration::from_secs(polls.min_expiration),
+            max_poll_expiration: Duration::from_secs(polls.max_expiration),
+            image_size_limit: media_attachments.image_size_limit,
+            image_matrix_limit: media_attachments.image_matrix_limit,
+        }
+    }
+}
+
+/// A poll
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct Poll {
+    /// Options
+    pub options: Vec<String>,
+    /// Duration of the poll, in seconds
+    pub expires_in: u64,
+    /// Whether several options can be chosen
+    pub multiple: bool,
+    /// Whether the results are hidden until the poll ends
+    pub hide_totals: bool,
+}
+
+impl Poll {
+    /// Create a poll
+    pub fn new(options: Vec<String>, expires_in: Duration) -> Self {
+        Poll {
+            options,
+            expires_in: expires_in.as_secs(),
+            multiple: false,
+            hide_totals: false,
+        }
+    }
+}
+
+/// A posted status
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Posted {
+    /// The status was posted
+    Status(Box<Status>),
+    /// The status was scheduled
+    Scheduled(ScheduledStatus),
+}
+
+/// A status builder
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct StatusBuilder {
+    status: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    media_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    poll: Option<Poll>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_reply_to_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quoted_status_id: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    sensitive: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    spoiler_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    visibility: Option<Visibility>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scheduled_at: Option<String>,
+    #[serde(skip)]
+    idempotency_key: String,
+}
+
+impl StatusBuilder {
+    /// Create a builder for a status with some text
+    ///
+    /// A new idempotency key is generated.
+    pub fn new(text: &str) -> Self {
+        StatusBuilder {
+            status: text.to_string(),
+            media_ids: Vec::new(),
+            poll: None,
+            in_reply_to_id: None,
+            quoted_status_id: None,
+            sensitive: false,
+            spoiler_text: String::new(),
+            visibility: None,
+            language: None,
+            scheduled_at: None,
+            idempotency_key: generate_key(),
+        }
+    }
+
+    /// Set the visibility
+    ///
+    /// The default visibility of the account is used if not set.
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = Some(visibility);
+        self.changed()
+    }
+
+    /// Set the content warning
+    pub fn spoiler_text(mut self, spoiler_text: &str) -> Self {
+        self.spoiler_text = spoiler_text.to_string();
+        self.changed()
+    }
+
+    /// Set the language, as an ISO 639 code
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self.changed()
+    }
+
+    /// Mark the media attachments as sensitive
+    pub fn sensitive(mut self, sensitive: bool) -> Self {
+        self.sensitive = sensitive;
+        self.changed()
+    }
+
+    /// Attach a media, by identifier
+    pub fn media(mut self, id: &str) -> Self {
+        self.media_ids.push(id.to_string());
+        self.changed()
+    }
+
+    /// Attach a poll
+    pub fn poll(mut self, poll: Poll) -> Self {
+        self.poll = Some(poll);
+        self.changed()
+    }
+
+    /// Reply to a status, by identifier
+    pub fn in_reply_to(mut self, id: &str) -> Self {
+        self.in_reply_to_id = Some(id.to_string());
+        self.changed()
+    }
+
+    /// Quote a status, by identifier
+    pub fn quote(mut self, id: &str) -> Self {
+        self.quoted_status_id = Some(id.to_string());
+        self.changed()
+    }
+
+    /// Schedule the status
+    ///
+    /// Mastodon requires the time to be at least 5 minutes in the future.
+    pub fn scheduled_at(mut self, time: SystemTime) -> Self {
+        self.scheduled_at = Some(format_timestamp(time));
+        self.changed()
+    }
+
+    /// Set the idempotency key
+    ///
+    /// Setting the content of the status afterwards generates a new key.
+    pub fn idempotency_key(mut self, key: &str) -> Self {
+        self.idempotency_key = key.to_string();
+        self
+    }
+
+    /// Idempotency key
+    pub fn key(&self) -> &str {
+        &self.idempotency_key
+    }
+
+    /// Generate a new idempotency key, as the status changed
+    fn changed(mut self) -> Self {
+        self.idempotency_key = generate_key();
+        self
+    }
+
+    /// Whether the status is scheduled
+    pub fn is_scheduled(&self) -> bool {
+        self.scheduled_at.is_some()
+    }
+
+    /// Check the status against the limits of an instance
+    ///
+    /// Returns a [`Error::Validation`] error listing the invalid fields. Like
+    /// Mastodon, the content warning counts with the text.
+    pub fn validate(&self, limits: &InstanceLimits) -> Result<(), Error> {
+        let mut details = Vec::new();
+        let mut invalid = |field: &str, error: &str, description: &str| {
+            details.push(FieldError {
+                field: field.to_string(),
+                error: error.to_string(),
+                description: description.to_string(),
+            })
+        };
+
+        if self.status.trim().is_empty() && self.media_ids.is_empty() && self.poll.is_none() {
+            invalid("text", "ERR_BLANK", "can't be blank");
+        }
+        let count = count_characters(&self.status) + count_characters(&self.spoiler_text);
+        if count > limits.max_characters {
+            invalid(
+                "text",
+                "ERR_TOO_LONG",
+                &format!(
+                    "is too long ({} characters, maximum is {})",
+                    count, limits.max_characters
+                ),
+            );
+        }
+        if self.visibility == Some(Visibility::Unknown) {
+            invalid("visibility", "ERR_INCLUSION", "is not included in the list");
+        }
+        if self.media_ids.len() > limits.max_media_attachments {
+            invalid(
+                "media_ids",
+                "ERR_TOO_MANY",
+                &format!(
+                    "has too many attachments (maximum is {})",
+                    limits.max_media_attachments
+                ),
+            );
+        }
+        if let Some(poll) = &self.poll {
+            if !self.media_ids.is_empty() {
+                invalid("poll", "ERR_INVALID", "can't be attached with media");
+            }
+            if poll.options.len() < 2 {
+                invalid("poll", "ERR_TOO_FEW", "must have at least 2 options");
+            }
+            if poll.options.len() > limits.max_poll_options {
+                invalid(
+                    "poll",
+                    "ERR_TOO_MANY",
+                    &format!(
+                        "has too many options (maximum is {})",
+                        limits.max_poll_options
+                    ),
+                );
+            }
+            if poll
+                .options
+                .iter()
+                .any(|option| count_characters(option) > limits.max_poll_option_characters)
+            {
+                invalid(
+                    "poll",
+                    "ERR_TOO_LONG",
+                    &format!(
+                        "has an option that is too long (maximum is {} characters)",
+                        limits.max_poll_option_characters
+                    ),
+                );
+            }
+            let expires_in = Duration::from_secs(poll.expires_in);
+            if expires_in < limits.min_poll_expiration || expires_in > limits.max_poll_expiration {
+                invalid("poll", "ERR_INVALID", "has an invalid duration");
+            }
+        }
+
+        if details.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation {
+                message: Some("Validation failed".to_string()),
+                details,
+            })
+        }
+    }
+}
+
+impl Posted {
+    pub(crate) fn from_json(builder: &StatusBuilder, body: &[u8]) -> Result<Self, Error> {
+        if builder.is_scheduled() {
+            Ok(Posted::Scheduled(serde_json::from_slice(body)?))
+        } else {
+            Ok(Posted::Status(serde_json::from_slice(body)?))
+        }
+    }
+}
+
+/// Generate an idempotency key
+///
+/// Keys are random, and fall back to the time and a counter if the random
+/// number generator of the operating system is not available.
+pub(crate) fn generate_key() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut bytes = [0; 16];
+    if getrandom::getrandom(&mut bytes).is_err() {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or(0);
+        let counter = COUNTER.fetch_add(1, Ordering::Relaxed) as u128;
+        bytes = (nanos ^ counter << 96).to_be_bytes();
+    }
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(result: Result<(), Error>) -> Vec<(String, String)> {
+        match result {
+            Ok(()) => Vec::new(),
+            Err(Error::Validation { details, .. }) => details
+                .into_iter()
+                .map(|detail| (detail.field, detail.error))
+                .collect(),
+            Err(error) => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    fn field(field: &str, error: &str) -> (String, String) {
+        (field.to_string(), error.to_string())
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let builder = StatusBuilder::new("Hello");
+        assert_eq!(builder.key().len(), 32);
+        assert_eq!(builder.clone().key(), builder.key());
+        assert_ne!(builder.clone().language("en").key(), builder.key());
+        assert_ne!(
+            builder.clone().visibility(Visibility::Private).key(),
+            builder.key()
+        );
+        let builder = builder.idempotency_key("key").sensitive(true);
+        assert_ne!(builder.key(), "key");
+        assert_ne!(StatusBuilder::new("Hello").key(), builder.key());
+    }
+
+    #[test]
+    fn test_validate_text() {
+        let limits = InstanceLimits::default();
+        assert_eq!(
+            fields(StatusBuilder::new(" ").validate(&limits)),
+            vec![field("text", "ERR_BLANK")]
+        );
+        assert_eq!(
+            fields(StatusBuilder::new(" ").media("1").validate(&limits)),
+            vec![]
+        );
+
+        // Links count as 23 characters
+        let text = format!(
+            "{} https://example.com/{}",
+            "a".repeat(476),
+            "b".repeat(100)
+        );
+        assert_eq!(fields(StatusBuilder::new(&text).validate(&limits)), vec![]);
+        assert_eq!(
+            fields(
+                StatusBuilder::new(&text)
+                    .spoiler_text("CW")
+                    .validate(&limits)
+            ),
+            vec![field("text", "ERR_TOO_LONG")]
+        );
+
+        let builder = StatusBuilder::new("Hello").visibility(Visibility::Unknown);
+        assert_eq!(
+            fields(builder.validate(&limits)),
+            vec![field("visibility", "ERR_INCLUSION")]
+        );
+    }
+
+    #[test]
+    fn test_validate_media_and_poll() {
+        let limits = InstanceLimits::default();
+        let builder = (0..5).fold(StatusBuilder::new("Photos"), |builder, i| {
+            builder.media(&i.to_string())
+        });
+        assert_eq!(
+            fields(builder.validate(&limits)),
+            vec![field("media_ids", "ERR_TOO_MANY")]
+        );
+
+        let options = vec!["Yes".to_string(), "x".repeat(51)];
+        let poll = Poll::new(options, Duration::from_secs(60));
+        assert_eq!(
+            fields(
+                StatusBuilder::new("Poll")
+                    .poll(poll)
+                    .media("1")
+                    .validate(&limits)
+            ),
+            vec![
+                field("poll", "ERR_INVALID"),
+                field("poll", "ERR_TOO_LONG"),
+                field("poll", "ERR_INVALID")
+            ]
+        );
+        let poll = Poll::new(vec!["Yes".to_string()], Duration::from_secs(3600));
+        assert_eq!(
+            fields(StatusBuilder::new("Poll").poll(poll).validate(&limits)),
+            vec![field("poll", "ERR_TOO_FEW")]
+        );
+    }
+}
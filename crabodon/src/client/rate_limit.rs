@@ -0,0 +1,404 @@
+//! Rate limits
+//!
+//! Mastodon limits the number of requests an account can make, and reports the
+//! state of the limit with `X-RateLimit-*` headers. Limits are counted per
+//! [`Bucket`]: media uploads and status deletions have their own limits.
+//!
+//! [`RateLimiter`] tracks the limit of each bucket. Before a request, it waits
+//! when the limit is almost reached, spreading the remaining requests until the
+//! limit resets. Requests rejected with `429 Too Many Requests` or
+//! `503 Service Unavailable` are retried, after the limit resets or with an
+//! exponential backoff.
+
+use super::timestamp::parse_timestamp;
+use super::transport::{Method, Request, Response};
+use crate::backoff::backoff;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+#[cfg(test)]
+use std::time::UNIX_EPOCH;
+use std::time::{Duration, SystemTime};
+
+/// A clock
+///
+/// The clock is used to wait before requests. Tests can replace it with a
+/// mock clock.
+pub trait Clock: Send + Sync {
+    /// Current time
+    fn now(&self) -> SystemTime;
+
+    /// Wait for a duration
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A list specifying rate limit buckets
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum Bucket {
+    /// Most requests
+    General,
+    /// Media uploads
+    Media,
+    /// Deletions and unboosts of statuses
+    StatusDeletion,
+}
+
+impl Bucket {
+    /// Bucket of a request
+    pub fn of(request: &Request) -> Self {
+        let path = request.url.path();
+        match request.method {
+            Method::Post if path == "/api/v1/media" || path == "/api/v2/media" => Bucket::Media,
+            Method::Delete if path.starts_with("/api/v1/statuses/") => Bucket::StatusDeletion,
+            Method::Post
+                if path.starts_with("/api/v1/statuses/") && path.ends_with("/unreblog") =>
+            {
+                Bucket::StatusDeletion
+            }
+            _ => Bucket::General,
+        }
+    }
+}
+
+/// State of a rate limit
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct RateLimit {
+    /// Number of requests allowed in the period
+    pub limit: u32,
+    /// Number of requests remaining in the period
+    pub remaining: u32,
+    /// End of the period
+    pub reset: SystemTime,
+}
+
+impl RateLimit {
+    /// Create a rate limit
+    pub fn new(limit: u32, remaining: u32, reset: SystemTime) -> Self {
+        RateLimit {
+            limit,
+            remaining,
+            reset,
+        }
+    }
+
+    /// Read a rate limit from the headers of a response
+    ///
+    /// Returns `None` if the headers are missing or invalid.
+    pub fn from_response(response: &Response) -> Option<Self> {
+        let limit = response.header("X-RateLimit-Limit")?.trim().parse().ok()?;
+        let remaining = response
+            .header("X-RateLimit-Remaining")?
+            .trim()
+            .parse()
+            .ok()?;
+        let reset = parse_timestamp(response.header("X-RateLimit-Reset")?)?;
+        Some(RateLimit::new(limit, remaining, reset))
+    }
+}
+
+/// Rate limit policy
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct RateLimitPolicy {
+    /// Number of remaining requests under which requests are delayed
+    ///
+    /// Delayed requests are spread until the limit resets. Requests are not
+    /// delayed if zero, until the limit is reached.
+    pub threshold: u32,
+    /// Maximum number of retries of a rejected request
+    pub max_retries: u32,
+    /// Delay before the first retry, when the reset time is unknown
+    pub backoff: Duration,
+    /// Maximum delay before a request
+    pub max_delay: Duration,
+}
+
+impl RateLimitPolicy {
+    /// Create a rate limit policy
+    pub fn new(threshold: u32, max_retries: u32, backoff: Duration, max_delay: Duration) -> Self {
+        RateLimitPolicy {
+            threshold,
+            max_retries,
+            backoff,
+            max_delay,
+        }
+    }
+
+    /// A policy that never delays nor retries requests
+    ///
+    /// Rate limits are still tracked.
+    pub fn disabled() -> Self {
+        RateLimitPolicy::new(0, 0, Duration::from_secs(0), Duration::from_secs(0))
+    }
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        RateLimitPolicy::new(5, 3, Duration::from_secs(1), Duration::from_secs(300))
+    }
+}
+
+/// A rate limiter
+///
+/// The rate limiter tracks the limits of each bucket from the responses, and
+/// decides how long to wait before requests. Clones share the same limits.
+#[derive(Clone)]
+pub struct RateLimiter {
+    policy: RateLimitPolicy,
+    clock: Arc<dyn Clock>,
+    limits: Arc<Mutex<HashMap<Bucket, RateLimit>>>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter
+    pub fn new(policy: RateLimitPolicy) -> Self {
+        RateLimiter {
+            policy,
+            clock: Arc::new(SystemClock),
+            limits: Arc::default(),
+        }
+    }
+
+    /// Set the clock
+    pub fn with_clock<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Policy of the rate limiter
+    pub fn policy(&self) -> &RateLimitPolicy {
+        &self.policy
+    }
+
+    /// Last known limit of a bucket
+    pub fn limit(&self, bucket: Bucket) -> Option<RateLimit> {
+        self.lock().get(&bucket).copied()
+    }
+
+    /// Delay before a request
+    ///
+    /// Requests are delayed when the number of remaining requests is under the
+    /// threshold, or zero, and the limit has not reset yet.
+    pub fn delay(&self, bucket: Bucket) -> Duration {
+        let limit = match self.limit(bucket) {
+            Some(limit) => limit,
+            None => return Duration::from_secs(0),
+        };
+        if limit.remaining > 0 && limit.remaining >= self.policy.threshold {
+            return Duration::from_secs(0);
+        }
+        let until_reset = limit
+            .reset
+            .duration_since(self.clock.now())
+            .unwrap_or_default();
+        (until_reset / (limit.remaining + 1)).min(self.policy.max_delay)
+    }
+
+    /// Delay before retrying a rejected request
+    ///
+    /// `attempt` is the number of previous retries. Returns `None` if the
+    /// response should not be retried.
+    pub fn retry_delay(
+        &self,
+        bucket: Bucket,
+        response: &Response,
+        attempt: u32,
+    ) -> Option<Duration> {
+        if !matches!(response.status, 429 | 503) || attempt >= self.policy.max_retries {
+            return None;
+        }
+        let retry_after = response
+            .header("Retry-After")
+            .and_then(|seconds| seconds.trim().parse().ok())
+            .map(Duration::from_secs);
+        let reset = self
+            .limit(bucket)
+            .filter(|limit| limit.remaining == 0)
+            .and_then(|limit| limit.reset.duration_since(self.clock.now()).ok())
+            .filter(|delay| *delay > Duration::from_secs(0));
+        let delay = retry_after
+            .or(reset)
+            .unwrap_or_else(|| backoff(self.policy.backoff, self.policy.max_delay, attempt));
+        Some(delay.min(self.policy.max_delay))
+    }
+
+    /// Update the limit of a bucket from a response
+    pub fn update(&self, bucket: Bucket, response: &Response) {
+        if let Some(limit) = RateLimit::from_response(response) {
+            self.lock().insert(bucket, limit);
+        }
+    }
+
+    pub(crate) fn sleep(&self, duration: Duration) {
+        if duration > Duration::from_secs(0) {
+            self.clock.sleep(duration);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Bucket, RateLimit>> {
+        // Limits are always left consistent, even if a thread panicked
+        match self.limits.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new(RateLimitPolicy::default())
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("policy", &self.policy)
+            .field("limits", &*self.lock())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use url::Url;
+
+    /// A clock that only moves when sleeping
+    pub(crate) struct MockClock {
+        now: Mutex<SystemTime>,
+        sleeps: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl MockClock {
+        pub(crate) fn new(now: SystemTime) -> (Self, Arc<Mutex<Vec<Duration>>>) {
+            let sleeps = Arc::new(Mutex::new(Vec::new()));
+            let clock = MockClock {
+                now: Mutex::new(now),
+                sleeps: sleeps.clone(),
+            };
+            (clock, sleeps)
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> SystemTime {
+            *self.now.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            *self.now.lock().unwrap() += duration;
+            self.sleeps.lock().unwrap().push(duration);
+        }
+    }
+
+    pub(crate) fn response(status: u16, remaining: u32, reset: &str) -> Response {
+        let headers = vec![
+            ("X-RateLimit-Limit".to_string(), "300".to_string()),
+            ("X-RateLimit-Remaining".to_string(), remaining.to_string()),
+            ("X-RateLimit-Reset".to_string(), reset.to_string()),
+        ];
+        Response::new(status, headers, b"{}".to_vec())
+    }
+
+    pub(crate) fn time(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_buckets() {
+        let url = Url::parse("https://mastodon.social/api/v2/media").unwrap();
+        assert_eq!(Bucket::of(&Request::new(Method::Post, url)), Bucket::Media);
+        let url = Url::parse("https://mastodon.social/api/v1/statuses/1").unwrap();
+        assert_eq!(
+            Bucket::of(&Request::new(Method::Delete, url.clone())),
+            Bucket::StatusDeletion
+        );
+        assert_eq!(Bucket::of(&Request::new(Method::Get, url)), Bucket::General);
+    }
+
+    #[test]
+    fn test_malformed_reset() {
+        for reset in &["2023-02-09T10:21:NaN", "2023-02-09T10:21:inf", "+inf"] {
+            assert_eq!(RateLimit::from_response(&response(429, 0, reset)), None);
+        }
+        let (clock, _) = MockClock::new(time(1_675_938_000));
+        let limiter = RateLimiter::default().with_clock(clock);
+        let throttled = response(429, 0, "2023-02-09T10:21:NaN");
+        limiter.update(Bucket::General, &throttled);
+        assert_eq!(limiter.limit(Bucket::General), None);
+        assert_eq!(
+            limiter.retry_delay(Bucket::General, &throttled, 0),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn test_proactive_delay() {
+        let (clock, _) = MockClock::new(time(1_675_938_000));
+        let limiter = RateLimiter::default().with_clock(clock);
+        assert_eq!(limiter.delay(Bucket::General), Duration::from_secs(0));
+
+        limiter.update(Bucket::General, &response(200, 100, "2023-02-09T10:25:00Z"));
+        assert_eq!(limiter.limit(Bucket::General).unwrap().remaining, 100);
+        assert_eq!(limiter.delay(Bucket::General), Duration::from_secs(0));
+
+        limiter.update(Bucket::General, &response(200, 2, "2023-02-09T10:25:00Z"));
+        assert_eq!(limiter.delay(Bucket::General), Duration::from_secs(100));
+        limiter.update(Bucket::General, &response(200, 0, "2023-02-09T10:25:00Z"));
+        assert_eq!(limiter.delay(Bucket::General), Duration::from_secs(300));
+        assert_eq!(limiter.delay(Bucket::Media), Duration::from_secs(0));
+
+        // The limit was reset
+        limiter.update(Bucket::General, &response(200, 0, "2023-02-09T10:00:00Z"));
+        assert_eq!(limiter.delay(Bucket::General), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let (clock, _) = MockClock::new(time(1_675_938_000));
+        let limiter = RateLimiter::default().with_clock(clock);
+        let unavailable = Response::new(503, Vec::new(), Vec::new());
+        let delays = (0..4)
+            .map(|attempt| limiter.retry_delay(Bucket::General, &unavailable, attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_secs(1)),
+                Some(Duration::from_secs(2)),
+                Some(Duration::from_secs(4)),
+                None
+            ]
+        );
+
+        let throttled = response(429, 0, "2023-02-09T10:21:00Z");
+        limiter.update(Bucket::General, &throttled);
+        assert_eq!(
+            limiter.retry_delay(Bucket::General, &throttled, 0),
+            Some(Duration::from_secs(60))
+        );
+        let ok = Response::new(200, Vec::new(), Vec::new());
+        assert_eq!(limiter.retry_delay(Bucket::General, &ok, 0), None);
+    }
+}
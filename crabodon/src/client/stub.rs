@@ -0,0 +1,50 @@
+//! A stub transport for tests
+
+use super::transport::{Body, Request, Response, Transport};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::error::Error as StdError;
+use std::io::Read;
+
+/// A transport that returns queued responses
+///
+/// Requests are recorded, so that tests can check them. Streamed bodies are
+/// read when the request is sent.
+#[derive(Default)]
+pub(crate) struct StubTransport {
+    responses: RefCell<VecDeque<Response>>,
+    requests: RefCell<Vec<Request>>,
+}
+
+impl StubTransport {
+    pub(crate) fn push(&self, status: u16, body: &str) {
+        self.push_response(Response::new(status, Vec::new(), body.as_bytes().to_vec()));
+    }
+
+    pub(crate) fn push_response(&self, response: Response) {
+        self.responses.borrow_mut().push_back(response);
+    }
+
+    pub(crate) fn requests(&self) -> Vec<Request> {
+        self.requests
+            .borrow()
+            .iter()
+            .filter_map(Request::try_clone)
+            .collect()
+    }
+}
+
+impl Transport for StubTransport {
+    fn send(&self, mut request: Request) -> Result<Response, Box<dyn StdError + Send + Sync>> {
+        if let Some(Body::Reader(reader)) = &mut request.body {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            request.body = Some(Body::Bytes(bytes));
+        }
+        self.requests.borrow_mut().push(request);
+        self.responses
+            .borrow_mut()
+            .pop_front()
+            .ok_or_else(|| "No response queued".into())
+    }
+}
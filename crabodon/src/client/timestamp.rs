@@ -0,0 +1,206 @@
+//! ISO 8601 timestamps
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parse an ISO 8601 timestamp, like `2023-02-09T10:25:00.179Z`
+///
+/// Returns `None` if the timestamp is invalid or out of range.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let timestamp = timestamp.trim();
+    if !matches!(timestamp.get(10..11)?, "T" | "t" | " ") {
+        return None;
+    }
+    let (date, time) = (timestamp.get(..10)?, timestamp.get(11..)?);
+    let mut date = date.splitn(3, '-');
+    let year = parse_digits(date.next()?)?;
+    let month = parse_digits(date.next()?)?;
+    let day = parse_digits(date.next()?)?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let offset_start = time.find(&['Z', 'z', '+', '-'][..]).unwrap_or(time.len());
+    let (time, offset) = time.split_at(offset_start);
+    let (time, fraction) = match time.find('.') {
+        Some(dot) => (&time[..dot], Some(&time[dot + 1..])),
+        None => (time, None),
+    };
+    let mut time = time.splitn(3, ':');
+    let hours = parse_digits(time.next()?)?;
+    let minutes = parse_digits(time.next()?)?;
+    let seconds = parse_digits(time.next()?)?;
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    let nanos = match fraction {
+        Some(fraction) => {
+            // Digits beyond nanoseconds are ignored
+            let digits = fraction.get(..fraction.len().min(9))?;
+            parse_digits(fraction)?;
+            parse_digits(digits)? * 10i64.pow(9 - digits.len() as u32)
+        }
+        None => 0,
+    };
+    let offset = match offset {
+        "" | "Z" | "z" => 0,
+        offset => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let mut parts = offset[1..].splitn(2, ':');
+            let hours = parse_digits(parts.next()?)?;
+            let minutes = match parts.next() {
+                Some(minutes) => parse_digits(minutes)?,
+                None => 0,
+            };
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    // Days since the UNIX epoch, from Howard Hinnant's algorithm
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era
+        .checked_mul(146_097)?
+        .checked_add(day_of_era - 719_468)?;
+
+    let seconds = days
+        .checked_mul(86400)?
+        .checked_add(hours * 3600 + minutes * 60 + seconds)?
+        .checked_sub(offset)?;
+    if seconds < 0 {
+        return None;
+    }
+    UNIX_EPOCH.checked_add(Duration::new(seconds as u64, nanos as u32))
+}
+
+/// Parse a non-empty string of ASCII digits
+fn parse_digits(digits: &str) -> Option<i64> {
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Format a time as an ISO 8601 timestamp, like `2023-02-09T10:25:00Z`
+///
+/// Fractions of seconds are dropped.
+pub(crate) fn format_timestamp(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(error) => -(error.duration().as_secs() as i64),
+    };
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // Civil date from days since the UNIX epoch, from Howard Hinnant's algorithm
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(time(0)));
+        assert_eq!(
+            parse_timestamp("2023-02-09T10:25:00.500Z"),
+            Some(time(1_675_938_300) + Duration::from_millis(500))
+        );
+        assert_eq!(
+            parse_timestamp("2023-02-09T11:25:00+01:00"),
+            Some(time(1_675_938_300))
+        );
+        assert_eq!(
+            parse_timestamp("2024-02-29T00:00:00.000Z"),
+            Some(time(1_709_164_800))
+        );
+        assert_eq!(parse_timestamp("tomorrow"), None);
+    }
+
+    #[test]
+    fn test_parse_invalid_timestamp() {
+        for timestamp in &[
+            "2023-02-09T10:21:NaN",
+            "2023-02-09T10:21:inf",
+            "2023-02-09T10:21:+5Z",
+            "2023-02-09T10:21:00.Z",
+            "2023-02-09T10:21:00.1e3Z",
+            "2023-02-09T24:00:00Z",
+            "2023-02-09T10:60:00Z",
+            "2023-02-29T10:00:00Z",
+            "2023-13-01T10:00:00Z",
+            "2023-02-09T10:00:00+24:00",
+            "2023-02-09X10:00:00Z",
+            "1969-12-31T23:59:59Z",
+            "99999999999999999999-01-01T00:00:00Z",
+            "9999-12-31T23:59:59-23:59",
+        ] {
+            // Must not panic
+            let time = parse_timestamp(timestamp);
+            if timestamp.starts_with("9999-") {
+                assert!(time.is_some());
+            } else {
+                assert_eq!(time, None, "{}", timestamp);
+            }
+        }
+        assert_eq!(
+            parse_timestamp("2023-02-09T10:25:00.1234567891Z"),
+            Some(time(1_675_938_300) + Duration::from_nanos(123_456_789))
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(time(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_timestamp(time(1_675_938_300)),
+            "2023-02-09T10:25:00Z"
+        );
+        assert_eq!(
+            format_timestamp(time(1_709_164_800)),
+            "2024-02-29T00:00:00Z"
+        );
+        let time = time(1_709_164_800) + Duration::from_millis(999);
+        assert_eq!(
+            parse_timestamp(&format_timestamp(time)),
+            Some(time - Duration::from_millis(999))
+        );
+    }
+}
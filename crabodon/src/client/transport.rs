@@ -0,0 +1,195 @@
+//! HTTP transport
+//!
+//! [`Client`](super::Client) does not perform HTTP requests itself. Instead, it
+//! builds [`Request`]s, and sends them with an implementation of [`Transport`].
+//! This allows using any HTTP library, like `reqwest` or `ureq`, or an in-memory
+//! implementation in tests.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::Read;
+use url::Url;
+
+/// A list specifying HTTP methods
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Method {
+    /// GET
+    Get,
+    /// POST
+    Post,
+    /// PUT
+    Put,
+    /// PATCH
+    Patch,
+    /// DELETE
+    Delete,
+}
+
+impl Method {
+    /// Name of the method
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// The body of a request
+pub enum Body {
+    /// A body in memory
+    Bytes(Vec<u8>),
+    /// A body streamed from a reader
+    ///
+    /// Transports should stream the body as it is read, instead of reading it
+    /// in memory first. Such bodies cannot be sent twice, so requests with them
+    /// are not retried.
+    Reader(Box<dyn Read + Send>),
+}
+
+impl Body {
+    /// Content of the body, if it is in memory
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            Body::Reader(_) => None,
+        }
+    }
+
+    /// Clone the body, if it is in memory
+    pub fn try_clone(&self) -> Option<Body> {
+        self.as_bytes().map(|bytes| Body::Bytes(bytes.to_vec()))
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => f.debug_tuple("Bytes").field(bytes).finish(),
+            Body::Reader(_) => f.debug_tuple("Reader").finish(),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::Bytes(bytes)
+    }
+}
+
+/// An HTTP request
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Request {
+    /// Method
+    pub method: Method,
+    /// URL, including the query
+    pub url: Url,
+    /// Headers
+    pub headers: Vec<(String, String)>,
+    /// Body
+    pub body: Option<Body>,
+}
+
+impl Request {
+    /// Create a request
+    ///
+    /// The request has no headers and no body.
+    pub fn new(method: Method, url: Url) -> Self {
+        Request {
+            method,
+            url,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    /// Value of a header
+    ///
+    /// Header names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Clone the request
+    ///
+    /// Returns `None` if the body is streamed from a reader.
+    pub fn try_clone(&self) -> Option<Request> {
+        let body = match &self.body {
+            Some(body) => Some(body.try_clone()?),
+            None => None,
+        };
+        Some(Request {
+            method: self.method,
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body,
+        })
+    }
+}
+
+/// An HTTP response
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Response {
+    /// Status code
+    pub status: u16,
+    /// Headers
+    pub headers: Vec<(String, String)>,
+    /// Body
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Create a response
+    pub fn new(status: u16, headers: Vec<(String, String)>, body: Vec<u8>) -> Self {
+        Response {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    /// Value of a header
+    ///
+    /// Header names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Whether the status code is a success
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// A trait to send HTTP requests
+///
+/// Implement this trait to use an HTTP library with [`Client`](super::Client).
+/// Responses with an error status code should be returned as a [`Response`].
+/// Errors should only be returned when no response could be obtained, for
+/// example when the server cannot be reached.
+pub trait Transport {
+    /// Send a request
+    fn send(&self, request: Request) -> Result<Response, Box<dyn StdError + Send + Sync>>;
+}
+
+impl<T> Transport for &T
+where
+    T: Transport + ?Sized,
+{
+    fn send(&self, request: Request) -> Result<Response, Box<dyn StdError + Send + Sync>> {
+        (**self).send(request)
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
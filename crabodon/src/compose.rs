@@ -0,0 +1,26 @@
+//! Compose statuses
+//!
+//! Before being posted, a status is plain text typed by the user. Mastodon converts
+//! this plain text to HTML on the server, turning mentions, hashtags and links into
+//! `<a>` tags.
+//!
+//! This module offers facilities to work with the text being composed, in a way that
+//! is consistent with what the server will produce. [`tokenize`] finds mentions,
+//! hashtags and links the same way Mastodon's autolinker does, and [`format`](mod@format) converts
+//! the text to the HTML the server would produce. [`autocomplete`] helps suggesting
+//! mentions, hashtags and emojis while the user is typing, [`reply`] computes the
+//! mentions to prefill a reply with, and [`hashtag`] suggests CamelCase spellings for
+//! hashtags.
+//!
+//! Mastodon limits the length of statuses. [`count`] counts characters like the server
+//! does, and [`split`] splits long texts into threads.
+//!
+//! See the documentation for each module for more information.
+
+pub mod autocomplete;
+pub mod count;
+pub mod format;
+pub mod hashtag;
+pub mod reply;
+pub mod split;
+pub mod tokenize;
@@ -0,0 +1,286 @@
+//! Autocomplete mentions, hashtags and emojis
+//!
+//! This module offers [`find_completion_context`], that detects if the cursor is inside
+//! a mention, a hashtag or an emoji shortcode being typed. The returned
+//! [`CompletionContext`] contains the prefix to search for, and can then be used to
+//! replace the typed text with the selected completion.
+//!
+//! Like in Mastodon's web client, a completion context is the word under the cursor,
+//! delimited by white spaces, starting with @, # or :.
+
+use crate::content::{Hashtag, Mention};
+use std::ops::Range;
+
+/// A list specifying types of completion
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum CompletionKind {
+    /// A mention, starting with @
+    Mention,
+    /// A hashtag, starting with #
+    Hashtag,
+    /// An emoji shortcode, starting with :
+    Emoji,
+}
+
+/// A completion context
+///
+/// Describes the token being typed under the cursor.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct CompletionContext {
+    /// Kind of completion
+    pub kind: CompletionKind,
+    /// Range of the token
+    ///
+    /// This range is expressed in bytes, and includes
+    /// the @, # or : prefix.
+    pub range: Range<usize>,
+    /// Prefix
+    ///
+    /// The text typed between the @, # or : prefix and
+    /// the cursor. This is the text to search for.
+    pub prefix: String,
+}
+
+/// Find the completion context
+///
+/// This function finds the token under the cursor, if any. `cursor` is
+/// expressed in bytes.
+///
+/// `None` is returned if the cursor is not inside a mention, a hashtag
+/// or an emoji shortcode, or if nothing was typed after the prefix.
+pub fn find_completion_context(text: &str, cursor: usize) -> Option<CompletionContext> {
+    if !text.is_char_boundary(cursor) {
+        return None;
+    }
+
+    let start = text[..cursor]
+        .rfind(char::is_whitespace)
+        .map(|index| index + next_char_len(text, index))
+        .unwrap_or(0);
+    let end = text[cursor..]
+        .find(char::is_whitespace)
+        .map(|index| cursor + index)
+        .unwrap_or_else(|| text.len());
+
+    let word = &text[start..end];
+    let kind = match word.chars().next()? {
+        '@' => CompletionKind::Mention,
+        '#' => CompletionKind::Hashtag,
+        ':' => CompletionKind::Emoji,
+        _ => return None,
+    };
+    if cursor == start {
+        return None;
+    }
+
+    let prefix = &text[start + 1..cursor];
+    let rest = &word[1..];
+    let is_valid = match kind {
+        CompletionKind::Mention => is_valid_mention(rest),
+        CompletionKind::Hashtag => is_valid_hashtag(rest),
+        CompletionKind::Emoji => is_valid_emoji(rest),
+    };
+    if prefix.is_empty() || !is_valid {
+        return None;
+    }
+
+    Some(CompletionContext {
+        kind,
+        range: start..end,
+        prefix: prefix.to_string(),
+    })
+}
+
+impl CompletionContext {
+    /// Complete the text
+    ///
+    /// This method replaces the token with `completion`, followed by a
+    /// space if no whitespace follows already. It returns the completed text
+    /// and the new position of the cursor, after the whitespace.
+    pub fn complete(&self, text: &str, completion: &str) -> (String, usize) {
+        let mut output = String::with_capacity(text.len() + completion.len() + 1);
+        output.push_str(&text[..self.range.start]);
+        output.push_str(completion);
+        let rest = &text[self.range.end..];
+        let rest = match rest.chars().next() {
+            Some(c) if c.is_whitespace() => {
+                output.push(c);
+                &rest[c.len_utf8()..]
+            }
+            _ => {
+                output.push(' ');
+                rest
+            }
+        };
+        let cursor = output.len();
+        output.push_str(rest);
+        (output, cursor)
+    }
+
+    /// Complete the text with a mention
+    ///
+    /// The mention is inserted with its full handle, like `@alice@example.com`.
+    pub fn complete_mention(&self, text: &str, mention: &Mention) -> (String, usize) {
+        let completion = format!("{}@{}", mention.user(), mention.host());
+        self.complete(text, &completion)
+    }
+
+    /// Complete the text with a hashtag
+    pub fn complete_hashtag(&self, text: &str, hashtag: &Hashtag) -> (String, usize) {
+        let completion = format!("#{}", hashtag.tag());
+        self.complete(text, &completion)
+    }
+
+    /// Complete the text with an emoji shortcode
+    ///
+    /// `shortcode` is the shortcode, without the : delimiters.
+    pub fn complete_emoji(&self, text: &str, shortcode: &str) -> (String, usize) {
+        let completion = format!(":{}:", shortcode);
+        self.complete(text, &completion)
+    }
+}
+
+fn next_char_len(text: &str, index: usize) -> usize {
+    text[index..]
+        .chars()
+        .next()
+        .map(char::len_utf8)
+        .unwrap_or(0)
+}
+
+fn is_valid_mention(text: &str) -> bool {
+    let mut parts = text.splitn(2, '@');
+    let user = parts.next().unwrap_or("");
+    let host = parts.next().unwrap_or("");
+    let is_valid_user = user
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    let is_valid_host = host
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    is_valid_user && is_valid_host
+}
+
+fn is_valid_hashtag(text: &str) -> bool {
+    text.chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '_' | '\u{00b7}' | '\u{30fb}' | '\u{200c}'))
+}
+
+fn is_valid_emoji(text: &str) -> bool {
+    text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::{parse_content, ParagraphNode};
+    use crate::content::LinkKind;
+
+    #[test]
+    fn test_find_mention() {
+        let text = "Hello @ali";
+        let expected = CompletionContext {
+            kind: CompletionKind::Mention,
+            range: 6..10,
+            prefix: "ali".to_string(),
+        };
+        assert_eq!(find_completion_context(text, 10), Some(expected));
+
+        let text = "Hello @alice@mastodon.so how are you";
+        let expected = CompletionContext {
+            kind: CompletionKind::Mention,
+            range: 6..24,
+            prefix: "alice@mas".to_string(),
+        };
+        assert_eq!(find_completion_context(text, 16), Some(expected));
+    }
+
+    #[test]
+    fn test_find_hashtag() {
+        let text = "I like\n#rus";
+        let expected = CompletionContext {
+            kind: CompletionKind::Hashtag,
+            range: 7..11,
+            prefix: "rus".to_string(),
+        };
+        assert_eq!(find_completion_context(text, 11), Some(expected));
+
+        let text = "#café";
+        let expected = CompletionContext {
+            kind: CompletionKind::Hashtag,
+            range: 0..6,
+            prefix: "café".to_string(),
+        };
+        assert_eq!(find_completion_context(text, 6), Some(expected));
+    }
+
+    #[test]
+    fn test_find_emoji() {
+        let text = "Nice :blob";
+        let expected = CompletionContext {
+            kind: CompletionKind::Emoji,
+            range: 5..10,
+            prefix: "blob".to_string(),
+        };
+        assert_eq!(find_completion_context(text, 10), Some(expected));
+    }
+
+    #[test]
+    fn test_find_nothing() {
+        assert_eq!(find_completion_context("Hello", 5), None);
+        assert_eq!(find_completion_context("Hello @", 7), None);
+        assert_eq!(find_completion_context("Hello @alice", 6), None);
+        assert_eq!(find_completion_context("Smile :)", 8), None);
+        assert_eq!(find_completion_context("Done :blob:", 11), None);
+        // Inside the é
+        assert_eq!(find_completion_context("#café", 5), None);
+    }
+
+    #[test]
+    fn test_complete_parsed_hashtag() {
+        let content = parse_content(concat!(
+            r#"<p><a href="https://mastodon.social/tags/caf%C3%A9" "#,
+            r#"class="mention hashtag" rel="tag">#<span>café</span></a></p>"#
+        ));
+        let hashtag = content[0]
+            .iter()
+            .find_map(|node| match node {
+                ParagraphNode::Link(LinkKind::Hashtag(hashtag), _) => Some(hashtag.clone()),
+                _ => None,
+            })
+            .unwrap();
+        let text = "Coffee #caf";
+        let context = find_completion_context(text, 11).unwrap();
+        let expected = ("Coffee #café ".to_string(), 14);
+        assert_eq!(context.complete_hashtag(text, &hashtag), expected);
+    }
+
+    #[test]
+    fn test_complete() {
+        let text = "Hello @ali how are you";
+        let context = find_completion_context(text, 10).unwrap();
+        let mention = Mention::new(
+            "https://example.com/@alice".to_string(),
+            "example.com".to_string(),
+            "alice".to_string(),
+        );
+        let expected = ("Hello @alice@example.com how are you".to_string(), 25);
+        assert_eq!(context.complete_mention(text, &mention), expected);
+
+        let text = "I like #rus";
+        let context = find_completion_context(text, 11).unwrap();
+        let hashtag = Hashtag::new(
+            "https://example.com/tags/rust".to_string(),
+            "rust".to_string(),
+        );
+        let expected = ("I like #rust ".to_string(), 13);
+        assert_eq!(context.complete_hashtag(text, &hashtag), expected);
+
+        let text = ":blob";
+        let context = find_completion_context(text, 5).unwrap();
+        let expected = (":blobcat: ".to_string(), 10);
+        assert_eq!(context.complete_emoji(text, "blobcat"), expected);
+    }
+}
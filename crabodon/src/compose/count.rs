@@ -0,0 +1,128 @@
+//! Count characters
+//!
+//! Mastodon limits the number of characters in a status, but does not count
+//! characters naively. This module offers [`count_characters`], that counts
+//! characters like Mastodon's `StatusLengthValidator` does:
+//!
+//! - Characters are grapheme clusters, so an emoji counts as 1 character.
+//! - Links count as 23 characters, whatever their length.
+//! - Mentions only count for their user part, the instance is not counted.
+
+use super::tokenize::{tokenize_countable, Token};
+use crate::content::LinkKind;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Number of characters a link counts for
+pub const URL_LENGTH: usize = 23;
+
+/// Count characters
+///
+/// This function counts characters in `text` using Mastodon's rules.
+pub fn count_characters(text: &str) -> usize {
+    let mut count = 0;
+    let mut position = 0;
+    for token in tokenize_countable(text) {
+        count += count_graphemes(&text[position..token.range.start]);
+        count += token_length(&token);
+        position = token.range.end;
+    }
+    count + count_graphemes(&text[position..])
+}
+
+/// Character counts of the prefixes of a text
+///
+/// Counting each prefix of a text with [`count_characters`] takes quadratic time.
+/// The text is counted once instead, and the count of a slice is the difference
+/// between the counts of its bounds.
+pub(super) struct PrefixCounts {
+    /// End of each grapheme, link or mention, with the count up to this end
+    ends: Vec<(usize, usize)>,
+}
+
+impl PrefixCounts {
+    pub(super) fn new(text: &str) -> Self {
+        let mut ends = vec![(0, 0)];
+        let mut position = 0;
+        for token in tokenize_countable(text) {
+            push_graphemes(&mut ends, text, position..token.range.start);
+            let count = ends.last().map(|(_, count)| *count).unwrap_or(0);
+            ends.push((token.range.end, count + token_length(&token)));
+            position = token.range.end;
+        }
+        push_graphemes(&mut ends, text, position..text.len());
+        PrefixCounts { ends }
+    }
+
+    /// Count the characters of a slice of the text
+    ///
+    /// A link or mention that is cut by the slice counts whole.
+    pub(super) fn count(&self, range: Range<usize>) -> usize {
+        self.at(range.end) - self.at(range.start)
+    }
+
+    fn at(&self, index: usize) -> usize {
+        let i = self.ends.partition_point(|(end, _)| *end < index);
+        match self.ends.get(i).or_else(|| self.ends.last()) {
+            Some((_, count)) => *count,
+            None => 0,
+        }
+    }
+}
+
+fn push_graphemes(ends: &mut Vec<(usize, usize)>, text: &str, range: Range<usize>) {
+    let mut count = ends.last().map(|(_, count)| *count).unwrap_or(0);
+    for (index, grapheme) in text[range.clone()].grapheme_indices(true) {
+        count += 1;
+        ends.push((range.start + index + grapheme.len(), count));
+    }
+}
+
+fn token_length(token: &Token) -> usize {
+    match &token.link {
+        LinkKind::Mention(mention) => count_graphemes(mention.user()),
+        _ => URL_LENGTH,
+    }
+}
+
+fn count_graphemes(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        assert_eq!(count_characters(""), 0);
+        assert_eq!(count_characters("Hello world"), 11);
+        assert_eq!(count_characters("🏳️‍🌈 café"), 6);
+    }
+
+    #[test]
+    fn test_count_links() {
+        let text = "See https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/ !";
+        assert_eq!(count_characters(text), 4 + URL_LENGTH + 2);
+    }
+
+    #[test]
+    fn test_count_mentions() {
+        assert_eq!(count_characters("@alice@example.com hi"), 9);
+        assert_eq!(count_characters("@alice hi"), 9);
+    }
+
+    #[test]
+    fn test_prefix_counts() {
+        let text = "🏳️‍🌈 See https://example.com/a/long/path and @alice@example.com";
+        let counts = PrefixCounts::new(text);
+        assert_eq!(counts.count(0..text.len()), count_characters(text));
+        for (index, _) in text.match_indices(' ') {
+            assert_eq!(counts.count(0..index), count_characters(&text[..index]));
+            assert_eq!(
+                counts.count(index..text.len()),
+                count_characters(&text[index..])
+            );
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Format text being composed to HTML
+//!
+//! This module offers [`format_text`], that converts plain text to HTML, the same
+//! way Mastodon's `TextFormatter` does when a status is posted.
+//!
+//! The produced HTML can be used to render a status before the server returns it,
+//! for example for offline drafts, and can be parsed with the [`content`](crate::content)
+//! module, like the content returned by the server.
+
+use super::tokenize::{tokenize, Token};
+use crate::content::{Hashtag, Link, LinkKind, Mention};
+
+/// Number of characters displayed for long links
+const DISPLAY_URL_LENGTH: usize = 30;
+
+/// Format text
+///
+/// This function converts plain text to Mastodon HTML. `instance` is the hostname of
+/// the Mastodon instance the user is in. It is used for local mentions and hashtags.
+///
+/// Paragraphs are separated by empty lines and are wrapped in `<p>`, and single new lines
+/// are converted to `<br />`. Mentions, hashtags and links are found with
+/// [`tokenize`] and converted to links.
+pub fn format_text(text: &str, instance: &str) -> String {
+    if text.trim().is_empty() {
+        return String::new();
+    }
+
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let html = format_tokens(&text, tokenize(&text, instance));
+    format_paragraphs(&html)
+}
+
+fn format_tokens(text: &str, tokens: Vec<Token>) -> String {
+    let mut html = String::new();
+    let mut position = 0;
+    for token in tokens {
+        html.push_str(&escape(&text[position..token.range.start]));
+        match &token.link {
+            LinkKind::Link(link) => push_link(&mut html, link),
+            LinkKind::Mention(mention) => push_mention(&mut html, mention),
+            LinkKind::Hashtag(hashtag) => push_hashtag(&mut html, hashtag),
+        }
+        position = token.range.end;
+    }
+    html.push_str(&escape(&text[position..]));
+    html
+}
+
+fn format_paragraphs(html: &str) -> String {
+    let mut output = String::new();
+    let paragraphs = split_paragraphs(html);
+    for paragraph in paragraphs {
+        output.push_str("<p>");
+        let mut previous = None;
+        let mut chars = paragraph.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\n' {
+                let after_text = previous.map(|previous| previous != '\n').unwrap_or(false);
+                let before_text = chars.peek().map(|&next| next != '\n').unwrap_or(false);
+                if after_text && before_text {
+                    output.push_str("<br />");
+                }
+            } else {
+                output.push(c);
+            }
+            previous = Some(c);
+        }
+        output.push_str("</p>");
+    }
+    output
+}
+
+fn split_paragraphs(html: &str) -> Vec<&str> {
+    let mut paragraphs = Vec::new();
+    let mut rest = html;
+    while let Some(index) = rest.find("\n\n") {
+        paragraphs.push(&rest[..index]);
+        rest = rest[index..].trim_start_matches('\n');
+    }
+    paragraphs.push(rest);
+
+    // Like Ruby's split, trailing empty paragraphs are dropped
+    while paragraphs
+        .last()
+        .map(|last| last.is_empty())
+        .unwrap_or(false)
+    {
+        paragraphs.pop();
+    }
+    paragraphs
+}
+
+fn push_link(html: &mut String, link: &Link) {
+    let url = &link.href;
+    let prefix = url_prefix(url);
+    let rest = &url[prefix.len()..];
+    let (display, suffix) = match rest.char_indices().nth(DISPLAY_URL_LENGTH) {
+        Some((index, _)) => (&rest[..index], &rest[index..]),
+        None => (rest, ""),
+    };
+    let class = if suffix.is_empty() { "" } else { "ellipsis" };
+
+    html.push_str("<a href=\"");
+    html.push_str(&escape(url));
+    html.push_str("\" target=\"_blank\" rel=\"nofollow noopener noreferrer\" translate=\"no\">");
+    html.push_str("<span class=\"invisible\">");
+    html.push_str(&escape(prefix));
+    html.push_str("</span><span class=\"");
+    html.push_str(class);
+    html.push_str("\">");
+    html.push_str(&escape(display));
+    html.push_str("</span><span class=\"invisible\">");
+    html.push_str(&escape(suffix));
+    html.push_str("</span></a>");
+}
+
+fn push_mention(html: &mut String, mention: &Mention) {
+    let username = mention.user.trim_start_matches('@');
+    html.push_str("<span class=\"h-card\" translate=\"no\"><a href=\"");
+    html.push_str(&escape(&mention.href));
+    html.push_str("\" class=\"u-url mention\">@<span>");
+    html.push_str(&escape(username));
+    html.push_str("</span></a></span>");
+}
+
+fn push_hashtag(html: &mut String, hashtag: &Hashtag) {
+    html.push_str("<a href=\"");
+    html.push_str(&escape(&hashtag.href));
+    html.push_str("\" class=\"mention hashtag\" rel=\"tag\">#<span>");
+    html.push_str(&escape(&hashtag.tag));
+    html.push_str("</span></a>");
+}
+
+fn url_prefix(url: &str) -> &str {
+    let prefixes = [
+        "https://www.",
+        "http://www.",
+        "https://",
+        "http://",
+        "xmpp:",
+    ];
+    prefixes
+        .iter()
+        .find(|prefix| starts_with_ignore_ascii_case(url, prefix))
+        .map(|prefix| &url[..prefix.len()])
+        .unwrap_or("")
+}
+
+fn starts_with_ignore_ascii_case(text: &str, prefix: &str) -> bool {
+    text.get(..prefix.len())
+        .map(|start| start.eq_ignore_ascii_case(prefix))
+        .unwrap_or(false)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::parse_content;
+
+    #[test]
+    fn test_format() {
+        let text = "Hello @alice & <bob>\nsee #rust\n\n\nhttps://example.com";
+        let expected = concat!(
+            "<p>Hello <span class=\"h-card\" translate=\"no\">",
+            "<a href=\"https://mastodon.social/@alice\" class=\"u-url mention\">@<span>alice</span></a>",
+            "</span> &amp; &lt;bob&gt;<br />see ",
+            "<a href=\"https://mastodon.social/tags/rust\" class=\"mention hashtag\" rel=\"tag\">",
+            "#<span>rust</span></a></p>",
+            "<p><a href=\"https://example.com\" target=\"_blank\" rel=\"nofollow noopener noreferrer\" ",
+            "translate=\"no\"><span class=\"invisible\">https://</span><span class=\"\">example.com</span>",
+            "<span class=\"invisible\"></span></a></p>",
+        );
+        assert_eq!(format_text(text, "mastodon.social"), expected);
+    }
+
+    #[test]
+    fn test_format_empty() {
+        assert_eq!(format_text(" \n ", "mastodon.social"), "");
+    }
+
+    #[test]
+    fn test_real_with_hashtags() {
+        // 109805244883278164 on mastodon.social
+        let text = "I have a feeling this will appeal to multiple people for multiple reasons.\n\n\
+                    [original source: https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/]\n\n\
+                    #ttrpg #magic #coding";
+        let content = include_str!("../content/tests/real_with_hashtags.html");
+        assert_eq!(
+            parse_content(&format_text(text, "dice.camp")),
+            parse_content(content)
+        );
+    }
+
+    #[test]
+    fn test_real_with_mentions() {
+        // 109818097593839444 on mastodon.social
+        let text = "@cybette@mastodon.org.uk nice ! That's way better :)\r\n\r\n\
+                    So basically, you had to take 2 sets of stickers. One for FOSDEM and one for \
+                    @cfgmgmtcamp@fosstodon.org  ?";
+        let content = include_str!("../content/tests/real_with_mentions.html");
+        assert_eq!(
+            parse_content(&format_text(text, "mastodon.social")),
+            parse_content(content)
+        );
+    }
+
+    #[test]
+    fn test_real_with_newlines() {
+        // 109882001535463183 on mastodon.social
+        let text = "Test 1 please ignore\nTest 1 (cont)";
+        let content = include_str!("../content/tests/real_with_newlines.html");
+        assert_eq!(
+            parse_content(&format_text(text, "mastodon.social")),
+            parse_content(content)
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Suggest CamelCase hashtags
+//!
+//! Hashtags made of several words, like `#savethebees`, are hard to read, and
+//! screen readers can't pronounce them. Writing them in CamelCase, like
+//! `#SaveTheBees`, fixes both issues, and Mastodon treats both spellings as the
+//! same hashtag.
+//!
+//! This module offers [`suggest_camel_case`], that splits an all-lowercase
+//! [`Hashtag`] into words and suggests a CamelCase spelling, and
+//! [`find_lowercase_hashtags`], that finds such hashtags in a text being composed.
+//!
+//! Words are found with a small bundled English dictionary of words common in
+//! hashtags. The preferred split is the one with the fewest words and, among those,
+//! the one with the longest words. No suggestion is made if a part of the hashtag
+//! is not a known word.
+
+use super::tokenize::tokenize;
+use crate::content::{Hashtag, LinkKind};
+use once_cell::sync::Lazy;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// A CamelCase suggestion for a hashtag
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct HashtagSuggestion {
+    /// Range of the hashtag
+    ///
+    /// This range is expressed in bytes, and includes
+    /// the # prefix.
+    pub range: Range<usize>,
+    /// Hashtag
+    pub hashtag: Hashtag,
+    /// Suggested tag
+    ///
+    /// The tag written in CamelCase, without the # prefix.
+    pub suggestion: String,
+}
+
+/// Cost of a split, as its number of words and the sum of the squares of the
+/// lengths of its words, so that longer words are preferred
+type Cost = (usize, Reverse<usize>);
+
+struct Dictionary {
+    words: HashSet<&'static str>,
+    max_length: usize,
+}
+
+static DICTIONARY: Lazy<Dictionary> = Lazy::new(|| {
+    let words = include_str!("words.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .collect::<HashSet<_>>();
+    let max_length = words.iter().map(|word| word.chars().count()).max();
+    Dictionary {
+        words,
+        max_length: max_length.unwrap_or(0),
+    }
+});
+
+/// Split a tag into words
+///
+/// This function returns the split of `tag` into the fewest known words,
+/// preferring longer words, or `None` if `tag` cannot be split into known
+/// words. Runs of digits are kept as a single word.
+pub fn split_words(tag: &str) -> Option<Vec<String>> {
+    let dictionary = &*DICTIONARY;
+    let chars = tag.to_lowercase().chars().collect::<Vec<_>>();
+    if chars.is_empty() {
+        return None;
+    }
+
+    // best[i] is the cost of the best split of the i first characters, and the
+    // start of its last word
+    let mut best: Vec<Option<(Cost, usize)>> = vec![None; chars.len() + 1];
+    best[0] = Some(((0, Reverse(0)), 0));
+    for end in 1..=chars.len() {
+        for start in 0..end {
+            let (count, Reverse(squares)) = match best[start] {
+                Some((cost, _)) => cost,
+                None => continue,
+            };
+            let length = end - start;
+            let is_digits = chars[start..end].iter().all(char::is_ascii_digit);
+            let is_word = || {
+                let word = || chars[start..end].iter().collect::<String>();
+                length <= dictionary.max_length && dictionary.words.contains(word().as_str())
+            };
+            if is_digits || is_word() {
+                let cost = (count + 1, Reverse(squares + length * length));
+                let is_better = best[end]
+                    .map(|(best_cost, _)| cost < best_cost)
+                    .unwrap_or(true);
+                if is_better {
+                    best[end] = Some((cost, start));
+                }
+            }
+        }
+    }
+
+    let mut words = Vec::new();
+    let mut end = chars.len();
+    while end > 0 {
+        let (_, start) = best[end]?;
+        words.push(chars[start..end].iter().collect::<String>());
+        end = start;
+    }
+    words.reverse();
+    Some(words)
+}
+
+/// Suggest a CamelCase spelling for a hashtag
+///
+/// This function returns a CamelCase spelling of the tag of `hashtag`, without
+/// the # prefix, if the tag is all lowercase and made of several known words.
+pub fn suggest_camel_case(hashtag: &Hashtag) -> Option<String> {
+    let tag = hashtag.tag();
+    let is_lowercase = tag.chars().any(char::is_lowercase) && !tag.chars().any(char::is_uppercase);
+    if !is_lowercase {
+        return None;
+    }
+
+    let words = split_words(tag)?;
+    if words.len() < 2 {
+        return None;
+    }
+    let suggestion = words.iter().map(|word| capitalize(word)).collect();
+    Some(suggestion)
+}
+
+/// Find lowercase hashtags in a text
+///
+/// This function finds hashtags in `text` with [`tokenize`], and returns
+/// those that can be written in CamelCase, with their suggested spelling.
+pub fn find_lowercase_hashtags(text: &str, instance: &str) -> Vec<HashtagSuggestion> {
+    tokenize(text, instance)
+        .into_iter()
+        .filter_map(|token| match token.link {
+            LinkKind::Hashtag(hashtag) => {
+                let suggestion = suggest_camel_case(&hashtag)?;
+                Some(HashtagSuggestion {
+                    range: token.range,
+                    hashtag,
+                    suggestion,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashtag(tag: &str) -> Hashtag {
+        Hashtag::new(
+            format!("https://mastodon.social/tags/{}", tag),
+            tag.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("savethebees"),
+            Some(vec![
+                "save".to_string(),
+                "the".to_string(),
+                "bees".to_string()
+            ])
+        );
+        assert_eq!(
+            split_words("alttext"),
+            Some(vec!["alt".to_string(), "text".to_string()])
+        );
+        assert_eq!(
+            split_words("covid19"),
+            Some(vec!["covid".to_string(), "19".to_string()])
+        );
+        assert_eq!(split_words("ttrpg"), None);
+        assert_eq!(split_words(""), None);
+    }
+
+    #[test]
+    fn test_suggest_camel_case() {
+        assert_eq!(
+            suggest_camel_case(&hashtag("savethebees")),
+            Some("SaveTheBees".to_string())
+        );
+        assert_eq!(
+            suggest_camel_case(&hashtag("blacklivesmatter")),
+            Some("BlackLivesMatter".to_string())
+        );
+        assert_eq!(suggest_camel_case(&hashtag("SaveTheBees")), None);
+        assert_eq!(suggest_camel_case(&hashtag("coding")), None);
+        assert_eq!(suggest_camel_case(&hashtag("ttrpg")), None);
+    }
+
+    #[test]
+    fn test_find_lowercase_hashtags() {
+        let text = "Look at them #savethebees #Caturday #magic #opensource";
+        let expected = vec![
+            HashtagSuggestion {
+                range: 13..25,
+                hashtag: Hashtag::new(
+                    "https://mastodon.social/tags/savethebees".to_string(),
+                    "savethebees".to_string(),
+                ),
+                suggestion: "SaveTheBees".to_string(),
+            },
+            HashtagSuggestion {
+                range: 43..54,
+                hashtag: Hashtag::new(
+                    "https://mastodon.social/tags/opensource".to_string(),
+                    "opensource".to_string(),
+                ),
+                suggestion: "OpenSource".to_string(),
+            },
+        ];
+        assert_eq!(find_lowercase_hashtags(text, "mastodon.social"), expected);
+    }
+}
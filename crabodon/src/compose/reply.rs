@@ -0,0 +1,122 @@
+//! Prefill replies
+//!
+//! When replying to a status, Mastodon clients prefill the reply with the author
+//! of the status and every account mentioned in it. This module offers
+//! [`reply_prefix`], that computes this prefix.
+
+use crate::content::visit::{visit_content, Visit};
+use crate::content::{LinkKind, Mention};
+
+/// Compute the prefix of a reply
+///
+/// This function takes the content of the status being replied to, its `author`
+/// and the `account` replying. It returns the mentions to prefill the reply with,
+/// followed by a space, or an empty string if there is nobody to mention.
+///
+/// The author comes first, followed by mentioned accounts, in the order they
+/// appear in the content. Duplicates and the account replying are removed.
+/// Accounts on the same instance as the account replying are mentioned with
+/// their local handle (`@alice`), others with their full handle
+/// (`@bob@example.com`).
+pub fn reply_prefix(content: &str, author: &Mention, account: &Mention) -> String {
+    let mentions = visit_content(content, MentionVisitor::default());
+    let mut handles: Vec<String> = Vec::new();
+    let mut seen = vec![normalize(account)];
+    for mention in std::iter::once(author).chain(mentions.iter()) {
+        let key = normalize(mention);
+        if seen.contains(&key) {
+            continue;
+        }
+        seen.push(key);
+        handles.push(handle(mention, account));
+    }
+
+    let mut prefix = handles.join(" ");
+    if !prefix.is_empty() {
+        prefix.push(' ');
+    }
+    prefix
+}
+
+fn normalize(mention: &Mention) -> (String, String) {
+    (
+        mention.user().trim_start_matches('@').to_lowercase(),
+        mention.host().to_lowercase(),
+    )
+}
+
+fn handle(mention: &Mention, account: &Mention) -> String {
+    let user = mention.user().trim_start_matches('@');
+    if mention.host().eq_ignore_ascii_case(account.host()) {
+        format!("@{}", user)
+    } else {
+        format!("@{}@{}", user, mention.host())
+    }
+}
+
+#[derive(Default)]
+struct MentionVisitor {
+    mentions: Vec<Mention>,
+}
+
+impl Visit for MentionVisitor {
+    type Output = Vec<Mention>;
+
+    fn begin_link(&mut self, link: &LinkKind) {
+        if let LinkKind::Mention(mention) = link {
+            self.mentions.push(mention.clone());
+        }
+    }
+
+    fn finalize(self) -> Self::Output {
+        self.mentions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mention(host: &str, user: &str) -> Mention {
+        Mention::new(
+            format!("https://{}/{}", host, user),
+            host.to_string(),
+            user.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_real_with_mentions() {
+        // 109818097593839444 on mastodon.social
+        let content = include_str!("../content/tests/real_with_mentions.html");
+        let author = mention("mastodon.social", "@SfietKonstantin");
+        let account = mention("mastodon.org.uk", "@alice");
+        assert_eq!(
+            reply_prefix(content, &author, &account),
+            "@SfietKonstantin@mastodon.social @cybette @cfgmgmtcamp@fosstodon.org "
+        );
+    }
+
+    #[test]
+    fn test_exclude_account() {
+        // 109818097593839444 on mastodon.social
+        let content = include_str!("../content/tests/real_with_mentions.html");
+        let author = mention("mastodon.social", "@SfietKonstantin");
+        let account = mention("mastodon.org.uk", "@Cybette");
+        assert_eq!(
+            reply_prefix(content, &author, &account),
+            "@SfietKonstantin@mastodon.social @cfgmgmtcamp@fosstodon.org "
+        );
+    }
+
+    #[test]
+    fn test_reply_to_self() {
+        let content = "<p>Hello <span class=\"h-card\"><a href=\"https://example.com/@bob\" \
+                       class=\"u-url mention\">@<span>bob</span></a></span> and \
+                       <span class=\"h-card\"><a href=\"https://example.com/@bob\" \
+                       class=\"u-url mention\">@<span>bob</span></a></span></p>";
+        let author = mention("mastodon.social", "@alice");
+        assert_eq!(reply_prefix(content, &author, &author), "@bob@example.com ");
+        assert_eq!(reply_prefix("<p>Hello</p>", &author, &author), "");
+    }
+}
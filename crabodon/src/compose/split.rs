@@ -0,0 +1,250 @@
+//! Split long texts into threads
+//!
+//! This module offers [`split_thread`], that splits a text that is too long to be
+//! posted as a single status into several parts, to be posted as a thread.
+//!
+//! Parts are measured with [`count_characters`], and the text is cut at the best
+//! boundary available. Paragraph boundaries are preferred in the second half of a
+//! part, then sentence boundaries, then word boundaries. Links, mentions and
+//! hashtags are never split.
+
+use super::count::{count_characters, PrefixCounts};
+use super::tokenize::tokenize;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Split a text into a thread
+///
+/// This function splits `text` into parts of at most `limit` characters. If
+/// `counters` is true, a `1/n` counter is appended to each part, when there are
+/// several parts. This counter is accounted for in the limit.
+///
+/// A part can be longer than `limit` only if it contains a link, a mention or a
+/// hashtag longer than `limit`.
+pub fn split_thread(text: &str, limit: usize, counters: bool) -> Vec<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    if count_characters(text) <= limit || !counters {
+        return split(text, limit);
+    }
+
+    let mut digits = 1;
+    loop {
+        // Counters are formatted as " n/count"
+        let reserved = 2 * digits + 2;
+        let parts = split(text, limit.saturating_sub(reserved).max(1));
+        let count = parts.len();
+        let count_digits = count.to_string().len();
+        if count_digits <= digits {
+            return parts
+                .into_iter()
+                .enumerate()
+                .map(|(i, part)| format!("{} {}/{}", part, i + 1, count))
+                .collect();
+        }
+        digits = count_digits;
+    }
+}
+
+#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd)]
+enum Boundary {
+    Word,
+    Sentence,
+    Paragraph,
+}
+
+fn split(text: &str, limit: usize) -> Vec<String> {
+    let protected = tokenize(text, "localhost")
+        .into_iter()
+        .map(|token| token.range)
+        .collect::<Vec<_>>();
+    let counts = PrefixCounts::new(text);
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        if counts.count(start..text.len()) <= limit {
+            parts.push(text[start..].to_string());
+            break;
+        }
+
+        let end = find_cut(text, start, limit, &protected, &counts);
+        parts.push(text[start..end].trim_end().to_string());
+        start = end + (text[end..].len() - text[end..].trim_start().len());
+    }
+    parts
+}
+
+fn find_cut(
+    text: &str,
+    start: usize,
+    limit: usize,
+    protected: &[Range<usize>],
+    counts: &PrefixCounts,
+) -> usize {
+    let mut best: Option<(Boundary, usize)> = None;
+    for (end, boundary) in boundaries(text, start, protected) {
+        let count = counts.count(start..end);
+        if count > limit {
+            break;
+        }
+        // An early paragraph break would leave a short part
+        let boundary = if boundary == Boundary::Paragraph && 2 * count < limit {
+            Boundary::Sentence
+        } else {
+            boundary
+        };
+        let is_better = best
+            .map(|(best_boundary, _)| boundary >= best_boundary)
+            .unwrap_or(true);
+        if is_better {
+            best = Some((boundary, end));
+        }
+    }
+
+    match best {
+        Some((_, end)) => end,
+        None => hard_cut(text, start, limit, protected, counts),
+    }
+}
+
+fn boundaries(text: &str, start: usize, protected: &[Range<usize>]) -> Vec<(usize, Boundary)> {
+    let mut boundaries = Vec::new();
+    let mut previous = None;
+    for (index, c) in text[start..].char_indices() {
+        let index = start + index;
+        let is_run_start = previous.map(|c: char| !c.is_whitespace()).unwrap_or(false);
+        previous = Some(c);
+        if !c.is_whitespace() || !is_run_start || is_protected(protected, index) {
+            continue;
+        }
+
+        let rest = &text[index..];
+        let whitespace = &rest[..rest.len() - rest.trim_start().len()];
+        let boundary = if whitespace.matches('\n').count() >= 2 {
+            Boundary::Paragraph
+        } else if ends_sentence(&text[..index]) {
+            Boundary::Sentence
+        } else {
+            Boundary::Word
+        };
+        boundaries.push((index, boundary));
+    }
+    boundaries
+}
+
+fn hard_cut(
+    text: &str,
+    start: usize,
+    limit: usize,
+    protected: &[Range<usize>],
+    counts: &PrefixCounts,
+) -> usize {
+    let mut end = start;
+    for (index, grapheme) in text[start..].grapheme_indices(true) {
+        let next = start + index + grapheme.len();
+        if counts.count(start..next) > limit {
+            break;
+        }
+        end = next;
+    }
+
+    // Never cut inside a protected range, and always make progress
+    if let Some(range) = protected
+        .iter()
+        .find(|range| range.start < end && end < range.end)
+    {
+        end = if range.start > start {
+            range.start
+        } else {
+            range.end
+        };
+    }
+    if end == start {
+        end = text[start..]
+            .grapheme_indices(true)
+            .nth(1)
+            .map(|(index, _)| start + index)
+            .unwrap_or_else(|| text.len());
+    }
+    end
+}
+
+fn is_protected(protected: &[Range<usize>], index: usize) -> bool {
+    protected
+        .iter()
+        .any(|range| range.start < index && index < range.end)
+}
+
+fn ends_sentence(text: &str) -> bool {
+    let closing: &[char] = &['"', '\'', ')', '”', '’', '»'];
+    let punctuation: &[char] = &['.', '!', '?', '…', '。', '！', '？'];
+    text.trim_end_matches(closing).ends_with(punctuation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_split() {
+        assert_eq!(
+            split_thread("  Hello world ", 500, true),
+            vec!["Hello world"]
+        );
+        assert!(split_thread("", 500, true).is_empty());
+    }
+
+    #[test]
+    fn test_split_paragraphs() {
+        let text = "First paragraph. It is long.\n\nSecond paragraph.";
+        let expected = vec!["First paragraph. It is long.", "Second paragraph."];
+        assert_eq!(split_thread(text, 35, false), expected);
+
+        // An early paragraph break is not preferred to a later sentence
+        let text = "Hi.\n\nThis is a longer paragraph. It continues here.";
+        let expected = vec!["Hi.\n\nThis is a longer paragraph.", "It continues here."];
+        assert_eq!(split_thread(text, 35, false), expected);
+    }
+
+    #[test]
+    fn test_split_sentences() {
+        let text = "First sentence. Second sentence, that is longer. Third.";
+        let expected = vec![
+            "First sentence.",
+            "Second sentence, that is longer.",
+            "Third.",
+        ];
+        assert_eq!(split_thread(text, 35, false), expected);
+    }
+
+    #[test]
+    fn test_split_words() {
+        let text = "A very long sentence without any punctuation";
+        let expected = vec!["A very long sentence", "without any", "punctuation"];
+        assert_eq!(split_thread(text, 20, false), expected);
+    }
+
+    #[test]
+    fn test_split_counters() {
+        let text = "One two three four five six seven";
+        let expected = vec!["One two three 1/3", "four five six 2/3", "seven 3/3"];
+        assert_eq!(split_thread(text, 18, true), expected);
+    }
+
+    #[test]
+    fn test_split_protected() {
+        let text = "Look at https://example.com/a/very/long/path/that/is/not/split";
+        let expected = vec![
+            "Look at",
+            "https://example.com/a/very/long/path/that/is/not/split",
+        ];
+        assert_eq!(split_thread(text, 25, false), expected);
+
+        let text = "abcdefgh #hashtagislong";
+        let expected = vec!["abcdefgh", "#hashtagislong"];
+        assert_eq!(split_thread(text, 10, false), expected);
+    }
+}
@@ -0,0 +1,427 @@
+//! Tokenize text being composed
+//!
+//! This module offers [`tokenize`], that finds mentions, hashtags and links in plain
+//! text, before it is posted.
+//!
+//! Mastodon detects those elements using a set of regular expressions, originally
+//! from `twitter-text`, and customized in Mastodon's `Extractor`. This module uses
+//! the same regular expressions, so that the result matches what the server will
+//! produce once the status is posted. The main differences are:
+//!
+//! - Links are only detected when they have a protocol, like Mastodon does.
+//! - The list of valid top-level domains is not embedded. Any alphabetic top-level
+//!   domain is accepted.
+
+use crate::content::{Hashtag, Link, LinkKind, Mention};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::ops::Range;
+use url::Url;
+
+/// A token
+///
+/// A token is a part of the composed text that will be converted
+/// to a link by Mastodon.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Token {
+    /// Range of the token
+    ///
+    /// This range is expressed in bytes, and includes
+    /// the @ or # prefixes of mentions and hashtags.
+    pub range: Range<usize>,
+    /// Link
+    ///
+    /// The link this token will be converted to.
+    pub link: LinkKind,
+}
+
+/// Tokenize text
+///
+/// This function finds all mentions, hashtags and links in a text being
+/// composed. `instance` is the hostname of the Mastodon instance the user
+/// is in. It is used for local mentions and hashtags.
+///
+/// Tokens are returned in the order they appear in the text, and never overlap.
+pub fn tokenize(text: &str, instance: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    tokens.extend(extract_urls(text));
+    tokens.extend(extract_hashtags(text, instance));
+    tokens.extend(extract_mentions(text, instance));
+    tokens.extend(extract_extra_uris(text));
+    remove_overlapping_tokens(tokens)
+}
+
+/// Tokenize text for counting
+///
+/// Only links and mentions get a length concession when counting
+/// characters. Hrefs of local mentions are not meaningful.
+pub(super) fn tokenize_countable(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    tokens.extend(extract_urls(text));
+    tokens.extend(extract_mentions(text, ""));
+    remove_overlapping_tokens(tokens)
+}
+
+const LATIN_ACCENTS: &str = concat!(
+    r"\u{00c0}-\u{00d6}\u{00d8}-\u{00f6}\u{00f8}-\u{00ff}\u{0100}-\u{024f}",
+    r"\u{0253}\u{0254}\u{0256}\u{0257}\u{0259}\u{025b}\u{0263}\u{0268}",
+    r"\u{026f}\u{0272}\u{0289}\u{028b}\u{02bb}\u{0300}-\u{036f}\u{1e00}-\u{1eff}",
+);
+
+const HASHTAG_SEPARATORS: &str = r"_\u{00b7}\u{30fb}\u{200c}";
+
+const URL_QUERY_CHARS: &str = r"[a-z0-9!?*'();:&=+$/%#\[\]_.,~|@^-]";
+
+const URL_QUERY_ENDING_CHARS: &str = "[a-z0-9_&=#/-]";
+
+static MENTION_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^@(?i)(?P<user>[a-z0-9_]+(?:[a-z0-9_.-]+[a-z0-9_]+)?)(?:@(?P<host>\w+(?:[.-]+\w+)*))?",
+    )
+    .unwrap()
+});
+
+static END_MENTION_RE: Lazy<Regex> = Lazy::new(|| {
+    let pattern = format!("^(?:[@＠{}]|://)", LATIN_ACCENTS);
+    Regex::new(&pattern).unwrap()
+});
+
+static HASHTAG_RE: Lazy<Regex> = Lazy::new(|| {
+    let first_sequence = format!(
+        r"[\w_][\w{sep}]*[\p{{Alphabetic}}{sep}][\w{sep}]*[\w_]",
+        sep = HASHTAG_SEPARATORS
+    );
+    let last_sequence = r"[\w_]*\p{Alphabetic}[\w_]*";
+    let pattern = format!(r"^#(?P<tag>{}|{})", first_sequence, last_sequence);
+    Regex::new(&pattern).unwrap()
+});
+
+static URL_RE: Lazy<Regex> = Lazy::new(|| {
+    let domain_valid_chars = r"[^\s\p{P}\p{S}\p{Cc}\p{Cf}]";
+    let subdomain = format!(
+        r"(?:(?:{dvc}(?:[_-]|{dvc})*)?{dvc}\.)",
+        dvc = domain_valid_chars
+    );
+    let domain_name = format!(
+        r"(?:(?:{dvc}(?:-|{dvc})*)?{dvc}\.)",
+        dvc = domain_valid_chars
+    );
+    let tld = r"(?:xn--[0-9a-z]+|\p{L}[\p{L}\p{M}]*)";
+    let domain = format!("(?:{}*{}{})", subdomain, domain_name, tld);
+
+    let general_path_chars = r"[^\s<>()?]";
+    let balanced_parens = format!(
+        r"\((?:{gp}+|(?:{gp}*\({gp}+\){gp}*))\)",
+        gp = general_path_chars
+    );
+    let path_ending_chars = format!("(?:[a-z0-9=_#/+{}-]|{})", LATIN_ACCENTS, balanced_parens);
+    let path = format!(
+        "(?:(?:{gp}*(?:{bp}{gp}*)*{end})|(?:{gp}+/))",
+        gp = general_path_chars,
+        bp = balanced_parens,
+        end = path_ending_chars
+    );
+
+    let pattern = format!(
+        r"(?i)(?:https?|dat|dweb|ipfs|ipns|ssb|gopher|gemini)://{}(?::[0-9]+)?(?:/{}*)?(?:\?{}*{})?",
+        domain, path, URL_QUERY_CHARS, URL_QUERY_ENDING_CHARS
+    );
+    Regex::new(&pattern).unwrap()
+});
+
+static EXTRA_URI_RE: Lazy<Regex> = Lazy::new(|| {
+    let pattern = format!(
+        "(?i)(?:xmpp|magnet):{}*{}",
+        URL_QUERY_CHARS, URL_QUERY_ENDING_CHARS
+    );
+    Regex::new(&pattern).unwrap()
+});
+
+fn extract_urls(text: &str) -> Vec<Token> {
+    URL_RE
+        .find_iter(text)
+        .filter(|m| is_valid_url_preceding_char(previous_char(text, m.start())))
+        .map(|m| Token {
+            range: m.range(),
+            link: LinkKind::Link(Link::new(m.as_str().to_string())),
+        })
+        .collect()
+}
+
+fn extract_extra_uris(text: &str) -> Vec<Token> {
+    EXTRA_URI_RE
+        .find_iter(text)
+        .filter(|m| is_valid_url_preceding_char(previous_char(text, m.start())))
+        .map(|m| Token {
+            range: m.range(),
+            link: LinkKind::Link(Link::new(m.as_str().to_string())),
+        })
+        .collect()
+}
+
+fn extract_hashtags(text: &str, instance: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = text[position..].find('#') {
+        let start = position + offset;
+        position = start + 1;
+
+        if !is_valid_hashtag_preceding_char(previous_char(text, start)) {
+            continue;
+        }
+        let captures = match HASHTAG_RE.captures(&text[start..]) {
+            Some(captures) => captures,
+            None => continue,
+        };
+        let mut tag = captures.name("tag").unwrap().as_str();
+        position = start + captures.get(0).unwrap().end();
+
+        // Like Mastodon, a hashtag followed by a link loses the link protocol
+        if text[position..].starts_with("://") {
+            if let Some(stripped) = strip_protocol(tag) {
+                tag = stripped;
+            }
+        }
+
+        let end = start + 1 + tag.len();
+        tokens.push(Token {
+            range: start..end,
+            link: LinkKind::Hashtag(Hashtag {
+                href: build_href(instance, "tags", tag),
+                tag: tag.to_string(),
+            }),
+        });
+    }
+    tokens
+}
+
+fn extract_mentions(text: &str, instance: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = text[position..].find('@') {
+        let start = position + offset;
+        position = start + 1;
+
+        if !is_valid_mention_preceding_char(previous_char(text, start)) {
+            continue;
+        }
+        let captures = match MENTION_RE.captures(&text[start..]) {
+            Some(captures) => captures,
+            None => continue,
+        };
+        let end = start + captures.get(0).unwrap().end();
+        position = end;
+
+        if END_MENTION_RE.is_match(&text[end..]) {
+            continue;
+        }
+
+        let user = format!("@{}", captures.name("user").unwrap().as_str());
+        let host = captures
+            .name("host")
+            .map(|host| host.as_str())
+            .unwrap_or(instance);
+        tokens.push(Token {
+            range: start..end,
+            link: LinkKind::Mention(Mention {
+                href: build_href(host, "", &user),
+                host: host.to_string(),
+                user,
+            }),
+        });
+    }
+    tokens
+}
+
+fn remove_overlapping_tokens(mut tokens: Vec<Token>) -> Vec<Token> {
+    tokens.sort_by_key(|token| token.range.start);
+    let mut output: Vec<Token> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        let overlaps = output
+            .last()
+            .map(|previous| previous.range.end > token.range.start)
+            .unwrap_or(false);
+        if !overlaps {
+            output.push(token);
+        }
+    }
+    output
+}
+
+fn previous_char(text: &str, index: usize) -> Option<char> {
+    text[..index].chars().next_back()
+}
+
+fn is_valid_url_preceding_char(c: Option<char>) -> bool {
+    match c {
+        None => true,
+        Some(c) => {
+            !(c.is_ascii_alphanumeric()
+                || matches!(c, '@' | '＠' | '$' | '#' | '＃')
+                || matches!(
+                    c,
+                    '\u{fffe}' | '\u{feff}' | '\u{ffff}' | '\u{202a}'..='\u{202e}'
+                ))
+        }
+    }
+}
+
+fn is_valid_hashtag_preceding_char(c: Option<char>) -> bool {
+    match c {
+        None => true,
+        Some(c) => !(c.is_alphanumeric() || matches!(c, '=' | '/' | ')')),
+    }
+}
+
+fn is_valid_mention_preceding_char(c: Option<char>) -> bool {
+    match c {
+        None => true,
+        Some(c) => !(is_word_char(c) || matches!(c, '=' | '/')),
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn strip_protocol(tag: &str) -> Option<&str> {
+    let stripped = tag
+        .strip_suffix("https")
+        .or_else(|| tag.strip_suffix("http"))?;
+    if stripped.is_empty() {
+        None
+    } else {
+        Some(stripped)
+    }
+}
+
+fn build_href(host: &str, prefix: &str, name: &str) -> String {
+    let href = if prefix.is_empty() {
+        format!("https://{}/{}", host, name)
+    } else {
+        format!("https://{}/{}/{}", host, prefix, name)
+    };
+    // Percent-encode the path like the server does
+    Url::parse(&href).map(String::from).unwrap_or(href)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(range: Range<usize>, href: &str) -> Token {
+        Token {
+            range,
+            link: LinkKind::Link(Link::new(href.to_string())),
+        }
+    }
+
+    fn mention(range: Range<usize>, host: &str, user: &str) -> Token {
+        Token {
+            range,
+            link: LinkKind::Mention(Mention {
+                href: format!("https://{}/{}", host, user),
+                host: host.to_string(),
+                user: user.to_string(),
+            }),
+        }
+    }
+
+    fn hashtag(range: Range<usize>, href: &str, tag: &str) -> Token {
+        Token {
+            range,
+            link: LinkKind::Hashtag(Hashtag {
+                href: href.to_string(),
+                tag: tag.to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_mentions() {
+        let text = "@alice hello @bob@example.com, and @carol@sub.example.org.";
+        let expected = vec![
+            mention(0..6, "mastodon.social", "@alice"),
+            mention(13..29, "example.com", "@bob"),
+            mention(35..57, "sub.example.org", "@carol"),
+        ];
+        assert_eq!(tokenize(text, "mastodon.social"), expected);
+    }
+
+    #[test]
+    fn test_mentions_invalid() {
+        let text = "email@example.com =@alice @josé @bob://";
+        assert_eq!(tokenize(text, "mastodon.social"), vec![]);
+    }
+
+    #[test]
+    fn test_hashtags() {
+        let text = "#rust is #1 but #café, not a#b nor #_";
+        let expected = vec![
+            hashtag(0..5, "https://mastodon.social/tags/rust", "rust"),
+            hashtag(16..22, "https://mastodon.social/tags/caf%C3%A9", "café"),
+        ];
+        assert_eq!(tokenize(text, "mastodon.social"), expected);
+    }
+
+    #[test]
+    fn test_hashtags_with_separators() {
+        let text = "#hello_world #ハッシュ・タグ";
+        let expected = vec![
+            hashtag(
+                0..12,
+                "https://mastodon.social/tags/hello_world",
+                "hello_world",
+            ),
+            hashtag(
+                13..35,
+                "https://mastodon.social/tags/%E3%83%8F%E3%83%83%E3%82%B7%E3%83%A5%E3%83%BB%E3%82%BF%E3%82%B0",
+                "ハッシュ・タグ",
+            ),
+        ];
+        assert_eq!(tokenize(text, "mastodon.social"), expected);
+    }
+
+    #[test]
+    fn test_hashtag_before_protocol() {
+        let text = "#taghttps://example.com";
+        let expected = vec![hashtag(0..4, "https://mastodon.social/tags/tag", "tag")];
+        assert_eq!(tokenize(text, "mastodon.social"), expected);
+    }
+
+    #[test]
+    fn test_urls() {
+        let text = "See https://example.com/path?query=1. Or (gemini://example.org/a_(b)) \
+                    and http://www.example.com:8080/.";
+        let expected = vec![
+            link(4..36, "https://example.com/path?query=1"),
+            link(42..68, "gemini://example.org/a_(b)"),
+            link(74..102, "http://www.example.com:8080/"),
+        ];
+        assert_eq!(tokenize(text, "mastodon.social"), expected);
+    }
+
+    #[test]
+    fn test_urls_invalid() {
+        let text = "example.com ftp://example.com xhttps://example.com";
+        assert_eq!(tokenize(text, "mastodon.social"), vec![]);
+    }
+
+    #[test]
+    fn test_extra_uris() {
+        let text = "Join xmpp:room@conference.example.com?join";
+        let expected = vec![link(5..42, "xmpp:room@conference.example.com?join")];
+        assert_eq!(tokenize(text, "mastodon.social"), expected);
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let text = "https://example.com/@alice/#tag @alice";
+        let expected = vec![
+            link(0..31, "https://example.com/@alice/#tag"),
+            mention(32..38, "mastodon.social", "@alice"),
+        ];
+        assert_eq!(tokenize(text, "mastodon.social"), expected);
+    }
+}
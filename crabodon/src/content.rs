@@ -8,20 +8,113 @@
 //! a visitor-like trait that maps closely to the HTML representation of a status, while the
 //! higher level [`parse`], that provides a processed, tree-like representation of Mastodon content.
 //!
+//! Other modules work on the processed representation: [`truncate`] truncates content for
+//! previews, [`highlight`] highlights search terms, [`filter`] applies keyword filters, and
+//! [`phishing`] detects suspicious links, [`clean`] strips tracking parameters from links,
+//! [`direction`] detects the direction of paragraphs, and [`accessible`] renders content for
+//! screen readers.
+//!
 //! This module only contain shared structures for those modules. See the documentation for each
 //! of them for more information.
 
+pub mod accessible;
+pub mod clean;
+pub mod direction;
+pub mod filter;
+pub mod highlight;
 pub mod parse;
+pub mod phishing;
+pub mod truncate;
 pub mod visit;
 
+use url::Url;
+
+/// A list specifying schemes of links
+///
+/// Links in Mastodon content can point to anything. This classification
+/// helps deciding which links are safe to open.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum LinkScheme {
+    /// An `http` or `https` link
+    Http,
+    /// A `mailto` link
+    Mailto,
+    /// A `gemini` link
+    Gemini,
+    /// A link with another scheme allowed by Mastodon
+    ///
+    /// Those are `dat`, `dweb`, `ipfs`, `ipns`, `ssb`, `gopher`,
+    /// `xmpp` and `magnet`.
+    Other,
+    /// A relative link
+    Relative,
+    /// A link that is not safe to open
+    ///
+    /// Links with schemes like `javascript` or `data`, or links that
+    /// cannot be parsed are not safe.
+    Unsafe,
+}
+
+impl LinkScheme {
+    /// Whether links with this scheme are safe to open
+    pub fn is_safe(&self) -> bool {
+        *self != LinkScheme::Unsafe
+    }
+}
+
 /// A link
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[non_exhaustive]
 pub struct Link {
     /// href
     ///
-    /// ie link
+    /// ie link, as found in the content
     pub href: String,
+    /// Scheme of the link
+    pub scheme: LinkScheme,
+    /// Absolute URL
+    ///
+    /// Relative links are resolved against the base URL of the content,
+    /// when it is known. This URL is `None` for unsafe links, and for
+    /// relative links without base URL.
+    pub url: Option<Url>,
+}
+
+impl Link {
+    /// Create a link
+    ///
+    /// The link is classified, but relative links are not resolved.
+    pub fn new(href: String) -> Self {
+        Self::with_base(href, None)
+    }
+
+    /// Create a link, resolving it against a base URL
+    ///
+    /// The link is classified, and relative links are resolved against `base`.
+    pub fn with_base(href: String, base: Option<&Url>) -> Self {
+        let (scheme, url) = match Url::parse(&href) {
+            Ok(url) => {
+                let scheme = match url.scheme() {
+                    "http" | "https" => LinkScheme::Http,
+                    "mailto" => LinkScheme::Mailto,
+                    "gemini" => LinkScheme::Gemini,
+                    "dat" | "dweb" | "ipfs" | "ipns" | "ssb" | "gopher" | "xmpp" | "magnet" => {
+                        LinkScheme::Other
+                    }
+                    _ => LinkScheme::Unsafe,
+                };
+                let url = if scheme.is_safe() { Some(url) } else { None };
+                (scheme, url)
+            }
+            Err(url::ParseError::RelativeUrlWithoutBase) => {
+                let url = base.and_then(|base| base.join(&href).ok());
+                (LinkScheme::Relative, url)
+            }
+            Err(_) => (LinkScheme::Unsafe, None),
+        };
+        Link { href, scheme, url }
+    }
 }
 
 /// A mention
@@ -33,16 +126,53 @@ pub struct Mention {
     /// href
     ///
     /// ie link for this mention
-    href: String,
+    pub(crate) href: String,
+    /// Host of the user
+    ///
+    /// Hostname of the Mastodon instance this user is in,
+    /// without the @ prefix.
+    pub(crate) host: String,
+    /// User
+    ///
+    /// Always have the @ prefix
+    pub(crate) user: String,
+}
+
+impl Mention {
+    /// Create a mention
+    ///
+    /// `user` is the name of the user, with or without the @ prefix,
+    /// and `host` the hostname of the Mastodon instance this user is in.
+    pub fn new(href: String, host: String, user: String) -> Self {
+        let user = if user.starts_with('@') {
+            user
+        } else {
+            format!("@{}", user)
+        };
+        Mention { href, host, user }
+    }
+
+    /// href
+    ///
+    /// ie link for this mention
+    pub fn href(&self) -> &str {
+        &self.href
+    }
+
     /// Host of the user
     ///
     /// Hostname of the Mastodon instance this user is in,
     /// without the @ prefix.
-    host: String,
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
     /// User
     ///
     /// Always have the @ prefix
-    user: String,
+    pub fn user(&self) -> &str {
+        &self.user
+    }
 }
 
 /// A hashtag
@@ -54,11 +184,38 @@ pub struct Hashtag {
     /// href
     ///
     /// ie link for this hashtag
-    href: String,
+    pub(crate) href: String,
     /// Hashtag
     ///
     /// Without the # prefix
-    tag: String,
+    pub(crate) tag: String,
+}
+
+impl Hashtag {
+    /// Create a hashtag
+    ///
+    /// `tag` is the hashtag, with or without the # prefix.
+    pub fn new(href: String, tag: String) -> Self {
+        let tag = match tag.strip_prefix('#') {
+            Some(tag) => tag.to_string(),
+            None => tag,
+        };
+        Hashtag { href, tag }
+    }
+
+    /// href
+    ///
+    /// ie link for this hashtag
+    pub fn href(&self) -> &str {
+        &self.href
+    }
+
+    /// Hashtag
+    ///
+    /// Without the # prefix, and decoded when parsed from a link, like `café`.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
 }
 
 /// A list specifying types of Mastodon links
@@ -0,0 +1,294 @@
+//! Render content for screen readers
+//!
+//! Screen readers struggle with some elements of Mastodon content. Hashtags like
+//! `#SaveTheBees` are read as a single word, links are spelled character by character,
+//! and long runs of the same emoji are read again and again.
+//!
+//! This module offers [`accessible_text`], that renders content parsed with
+//! [`parse_content`](super::parse::parse_content) as a text suited for screen readers,
+//! for example to be used as an accessible label:
+//!
+//! - Hashtags are announced as `hashtag`, and CamelCase hashtags are split into words.
+//! - Links whose text looks like a URL are announced as `link to <host>`.
+//! - Mentions are announced as `mention of <user> on <host>`.
+//! - Runs of the same emoji are collapsed, like `😂 5 times`.
+//!
+//! These labels are in English. [`accessible_text_with_labels`] renders content with
+//! labels in another language, provided by an implementation of [`AccessibleLabels`].
+
+use super::parse::{display_text, ParagraphNode};
+use super::{Hashtag, Link, LinkKind, Mention};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Minimum number of repetitions of an emoji to collapse them
+const EMOJI_RUN_LENGTH: usize = 3;
+
+/// Labels announced by screen readers
+///
+/// Methods return English labels by default. Implement this trait to translate
+/// them.
+pub trait AccessibleLabels {
+    /// Label of a link, like `link to example.com`
+    fn link(&self, host: &str) -> String {
+        format!("link to {}", host)
+    }
+
+    /// Label of a mention, like `mention of alice on example.com`
+    fn mention(&self, user: &str, host: &str) -> String {
+        format!("mention of {} on {}", user, host)
+    }
+
+    /// Label of a hashtag, like `hashtag Save The Bees`
+    ///
+    /// `words` are the words of the hashtag, separated by spaces.
+    fn hashtag(&self, words: &str) -> String {
+        format!("hashtag {}", words)
+    }
+
+    /// Label of a run of the same emoji, like `😂 5 times`
+    fn repeated(&self, emoji: &str, count: usize) -> String {
+        format!("{} {} times", emoji, count)
+    }
+}
+
+/// English labels
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EnglishLabels;
+
+impl AccessibleLabels for EnglishLabels {}
+
+/// Render content for screen readers
+///
+/// Paragraphs are separated by an empty line. Labels are in English.
+pub fn accessible_text(content: &[Vec<ParagraphNode>]) -> String {
+    accessible_text_with_labels(content, &EnglishLabels)
+}
+
+/// Render content for screen readers, with translated labels
+///
+/// Paragraphs are separated by an empty line.
+pub fn accessible_text_with_labels<L>(content: &[Vec<ParagraphNode>], labels: &L) -> String
+where
+    L: AccessibleLabels + ?Sized,
+{
+    content
+        .iter()
+        .map(|paragraph| accessible_paragraph(paragraph, labels))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn accessible_paragraph<L>(paragraph: &[ParagraphNode], labels: &L) -> String
+where
+    L: AccessibleLabels + ?Sized,
+{
+    let mut text = String::new();
+    for node in paragraph {
+        match node {
+            ParagraphNode::Text(value) | ParagraphNode::Highlight(value) => text.push_str(value),
+            ParagraphNode::NewLine => text.push('\n'),
+            ParagraphNode::Ellipsis => text.push('…'),
+            ParagraphNode::Link(LinkKind::Link(link), children) => {
+                text.push_str(&accessible_link(link, &display_text(children), labels))
+            }
+            ParagraphNode::Link(LinkKind::Mention(mention), _) => {
+                text.push_str(&accessible_mention(mention, labels))
+            }
+            ParagraphNode::Link(LinkKind::Hashtag(hashtag), children) => text.push_str(
+                &accessible_hashtag(hashtag, &display_text(children), labels),
+            ),
+        }
+    }
+    collapse_emojis(&text, labels)
+}
+
+fn accessible_link<L>(link: &Link, text: &str, labels: &L) -> String
+where
+    L: AccessibleLabels + ?Sized,
+{
+    let looks_like_url = !text.contains(char::is_whitespace) && text.contains(&['.', '/'][..]);
+    let host = link.url.as_ref().and_then(|url| url.host_str());
+    match host {
+        Some(host) if looks_like_url => {
+            let host = host.strip_prefix("www.").unwrap_or(host);
+            labels.link(host)
+        }
+        _ => text.to_string(),
+    }
+}
+
+fn accessible_mention<L>(mention: &Mention, labels: &L) -> String
+where
+    L: AccessibleLabels + ?Sized,
+{
+    let user = mention.user().trim_start_matches('@');
+    labels.mention(user, mention.host())
+}
+
+fn accessible_hashtag<L>(hashtag: &Hashtag, text: &str, labels: &L) -> String
+where
+    L: AccessibleLabels + ?Sized,
+{
+    // The displayed text keeps the case chosen by the author
+    let tag = text.trim_start_matches('#');
+    let tag = if tag.is_empty() { hashtag.tag() } else { tag };
+    labels.hashtag(&split_camel_case(tag))
+}
+
+/// Split a CamelCase word
+///
+/// Words are split before an uppercase letter that follows a lowercase
+/// letter, before the last letter of a run of uppercase letters followed
+/// by a lowercase letter, between letters and digits, and on underscores.
+fn split_camel_case(word: &str) -> String {
+    let chars = word.chars().collect::<Vec<_>>();
+    let mut output = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            if !output.is_empty() && !output.ends_with(' ') {
+                output.push(' ');
+            }
+            continue;
+        }
+        if i > 0 && !output.ends_with(' ') {
+            let previous = chars[i - 1];
+            let next = chars.get(i + 1).copied();
+            let is_boundary = (previous.is_lowercase() && c.is_uppercase())
+                || (previous.is_uppercase()
+                    && c.is_uppercase()
+                    && next.map(char::is_lowercase).unwrap_or(false))
+                || (previous.is_alphabetic() && c.is_numeric())
+                || (previous.is_numeric() && c.is_alphabetic());
+            if is_boundary {
+                output.push(' ');
+            }
+        }
+        output.push(c);
+    }
+    output.trim_end().to_string()
+}
+
+fn collapse_emojis<L>(text: &str, labels: &L) -> String
+where
+    L: AccessibleLabels + ?Sized,
+{
+    let graphemes = text.graphemes(true).collect::<Vec<_>>();
+    let mut output = String::new();
+    let mut i = 0;
+    while i < graphemes.len() {
+        let grapheme = graphemes[i];
+        let count = graphemes[i..]
+            .iter()
+            .take_while(|&&other| other == grapheme)
+            .count();
+        if count >= EMOJI_RUN_LENGTH && is_emoji(grapheme) {
+            output.push_str(&labels.repeated(grapheme, count));
+        } else {
+            for _ in 0..count {
+                output.push_str(grapheme);
+            }
+        }
+        i += count;
+    }
+    output
+}
+
+fn is_emoji(grapheme: &str) -> bool {
+    grapheme.chars().any(|c| {
+        matches!(
+            c,
+            '\u{1f000}'..='\u{1faff}' | '\u{2600}'..='\u{27bf}' | '\u{2b00}'..='\u{2bff}' | '\u{fe0f}'
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::parse_content;
+
+    #[test]
+    fn test_real_with_hashtags() {
+        // 109805244883278164 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_hashtags.html"));
+        let expected = concat!(
+            "I have a feeling this will appeal to multiple people for multiple reasons.\n\n",
+            "[original source: link to reddit.com]\n\n",
+            "hashtag ttrpg hashtag magic hashtag coding"
+        );
+        assert_eq!(accessible_text(&content), expected);
+    }
+
+    #[test]
+    fn test_real_with_mentions() {
+        // 109818097593839444 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_mentions.html"));
+        let expected = concat!(
+            "mention of cybette on mastodon.org.uk nice ! That's way better :)\n\n",
+            "So basically, you had to take 2 sets of stickers. One for FOSDEM and one for ",
+            "mention of cfgmgmtcamp on fosstodon.org  ?"
+        );
+        assert_eq!(accessible_text(&content), expected);
+    }
+
+    #[test]
+    fn test_non_ascii_hashtag() {
+        let content = parse_content(concat!(
+            r#"<p><a href="https://mastodon.social/tags/caf%C3%A9" "#,
+            r#"class="mention hashtag" rel="tag">#<span>CaféCrème</span></a> "#,
+            r#"<a href="https://mastodon.social/tags/caf%C3%A9" "#,
+            r#"class="mention hashtag" rel="tag"></a></p>"#
+        ));
+        assert_eq!(accessible_text(&content), "hashtag Café Crème hashtag café");
+    }
+
+    #[test]
+    fn test_split_camel_case() {
+        assert_eq!(split_camel_case("SaveTheBees"), "Save The Bees");
+        assert_eq!(split_camel_case("FOSDEM2023"), "FOSDEM 2023");
+        assert_eq!(split_camel_case("HTMLParser"), "HTML Parser");
+        assert_eq!(split_camel_case("rust_lang"), "rust lang");
+        assert_eq!(split_camel_case("ttrpg"), "ttrpg");
+    }
+
+    #[test]
+    fn test_links_and_emojis() {
+        let content = parse_content(concat!(
+            r#"<p>So funny 😂😂😂😂😂 <a href="https://example.com/a">read this</a> "#,
+            r#"<a href="https://www.example.com/b">example.com/b</a> 👍👍</p>"#
+        ));
+        assert_eq!(
+            accessible_text(&content),
+            "So funny 😂 5 times read this link to example.com 👍👍"
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        struct FrenchLabels;
+
+        impl AccessibleLabels for FrenchLabels {
+            fn link(&self, host: &str) -> String {
+                format!("lien vers {}", host)
+            }
+
+            fn hashtag(&self, words: &str) -> String {
+                format!("mot-clé {}", words)
+            }
+
+            fn repeated(&self, emoji: &str, count: usize) -> String {
+                format!("{} {} fois", emoji, count)
+            }
+        }
+
+        let content = parse_content(concat!(
+            r#"<p>😂😂😂 <a href="https://example.com/a">example.com/a</a> "#,
+            r#"<a href="https://mastodon.social/tags/SaveTheBees" class="mention hashtag">"#,
+            r#"#<span>SaveTheBees</span></a></p>"#
+        ));
+        assert_eq!(
+            accessible_text_with_labels(&content, &FrenchLabels),
+            "😂 3 fois lien vers example.com mot-clé Save The Bees"
+        );
+    }
+}
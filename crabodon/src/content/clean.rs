@@ -0,0 +1,378 @@
+//! Strip tracking parameters from links
+//!
+//! Many links shared on Mastodon carry tracking parameters, like `utm_source` or
+//! `fbclid`. This module offers [`LinkCleaner`], that removes them from links found
+//! in content parsed with [`parse_content`](super::parse::parse_content), or from
+//! links in a text being composed.
+//!
+//! Parameters to remove are described by [`CleanRule`]s. A built-in set of rules is
+//! provided by [`LinkCleaner::with_default_rules`], and can be extended with
+//! [`LinkCleaner::add_rule`]. Only `http` and `https` links are cleaned.
+
+use super::parse::{full_text, LinkNode, ParagraphNode};
+use super::{Link, LinkKind, LinkScheme};
+use crate::compose::tokenize::tokenize;
+use url::Url;
+
+/// A rule describing parameters to remove
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct CleanRule {
+    /// Hosts this rule applies to
+    ///
+    /// A rule for `youtube.com` also applies to its subdomains, like
+    /// `www.youtube.com`. The rule applies to all hosts if this list is empty.
+    pub hosts: Vec<String>,
+    /// Names of the parameters to remove
+    ///
+    /// A name ending with `*` matches all parameters starting with this name.
+    pub params: Vec<String>,
+}
+
+impl CleanRule {
+    /// Create a rule
+    pub fn new(hosts: Vec<String>, params: Vec<String>) -> Self {
+        CleanRule { hosts, params }
+    }
+
+    fn applies_to(&self, host: &str) -> bool {
+        self.hosts.is_empty()
+            || self.hosts.iter().any(|rule_host| {
+                host == rule_host
+                    || (host.ends_with(rule_host.as_str())
+                        && host[..host.len() - rule_host.len()].ends_with('.'))
+            })
+    }
+
+    fn matches(&self, param: &str) -> bool {
+        self.params
+            .iter()
+            .any(|rule_param| match rule_param.strip_suffix('*') {
+                Some(prefix) => param.starts_with(prefix),
+                None => param == rule_param,
+            })
+    }
+}
+
+const DEFAULT_RULES: &[(&[&str], &[&str])] = &[
+    (
+        &[],
+        &[
+            "utm_*", "fbclid", "gclid", "dclid", "msclkid", "yclid", "twclid", "igshid", "mc_cid",
+            "mc_eid", "_hsenc", "_hsmi", "mkt_tok",
+        ],
+    ),
+    (&["youtube.com", "youtu.be"], &["si", "pp"]),
+    (&["spotify.com"], &["si"]),
+    (&["twitter.com", "x.com"], &["s", "t"]),
+    (&["instagram.com"], &["igsh"]),
+    (&["amazon.com"], &["ref_", "pd_rd_*", "pf_rd_*"]),
+];
+
+/// A link cleaner
+#[derive(Clone, Debug, Default)]
+pub struct LinkCleaner {
+    rules: Vec<CleanRule>,
+}
+
+impl LinkCleaner {
+    /// Create a link cleaner
+    ///
+    /// The link cleaner only uses the provided `rules`.
+    pub fn new(rules: Vec<CleanRule>) -> Self {
+        LinkCleaner { rules }
+    }
+
+    /// Create a link cleaner with the built-in rules
+    ///
+    /// Built-in rules remove common tracking parameters, like `utm_*` or `fbclid`,
+    /// from all links, and parameters like `si` for some hosts, like YouTube.
+    pub fn with_default_rules() -> Self {
+        let rules = DEFAULT_RULES
+            .iter()
+            .map(|(hosts, params)| {
+                CleanRule::new(
+                    hosts.iter().map(|host| host.to_string()).collect(),
+                    params.iter().map(|param| param.to_string()).collect(),
+                )
+            })
+            .collect();
+        LinkCleaner { rules }
+    }
+
+    /// Add a rule
+    pub fn add_rule(&mut self, rule: CleanRule) {
+        self.rules.push(rule);
+    }
+
+    /// Clean a URL
+    ///
+    /// This method returns `href` without tracking parameters. The rest of
+    /// `href` is kept as is.
+    pub fn clean_href(&self, href: &str) -> String {
+        let host = match Url::parse(href) {
+            Ok(url) if url.scheme() == "http" || url.scheme() == "https" => match url.host_str() {
+                Some(host) => host.to_lowercase(),
+                None => return href.to_string(),
+            },
+            _ => return href.to_string(),
+        };
+        let rules = self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(&host))
+            .collect::<Vec<_>>();
+
+        let (before_fragment, fragment) = match href.find('#') {
+            Some(index) => href.split_at(index),
+            None => (href, ""),
+        };
+        let (base, query) = match before_fragment.find('?') {
+            Some(index) => (&before_fragment[..index], &before_fragment[index + 1..]),
+            None => return href.to_string(),
+        };
+
+        let params = query
+            .split('&')
+            .filter(|param| {
+                let name = param.split('=').next().unwrap_or("");
+                !param.is_empty() && !rules.iter().any(|rule| rule.matches(name))
+            })
+            .collect::<Vec<_>>();
+        if params.is_empty() {
+            format!("{}{}", base, fragment)
+        } else {
+            format!("{}?{}{}", base, params.join("&"), fragment)
+        }
+    }
+
+    /// Clean a link
+    pub fn clean_link(&self, link: &Link) -> Link {
+        if link.scheme != LinkScheme::Http {
+            return link.clone();
+        }
+        let href = self.clean_href(&link.href);
+        if href == link.href {
+            return link.clone();
+        }
+        let url = Url::parse(&href).ok();
+        Link {
+            href,
+            scheme: link.scheme,
+            url,
+        }
+    }
+
+    /// Clean links in content
+    ///
+    /// This method returns a copy of `content` where links are cleaned. When
+    /// the text of a link is its URL, like the hidden and displayed parts of
+    /// long links, it is cleaned too. Other texts are not modified.
+    pub fn clean_content(&self, content: &[Vec<ParagraphNode>]) -> Vec<Vec<ParagraphNode>> {
+        content
+            .iter()
+            .map(|paragraph| {
+                paragraph
+                    .iter()
+                    .map(|node| match node {
+                        ParagraphNode::Link(LinkKind::Link(link), children) => {
+                            let cleaned = self.clean_link(link);
+                            let children = clean_children(children, &link.href, &cleaned.href);
+                            ParagraphNode::Link(LinkKind::Link(cleaned), children)
+                        }
+                        node => node.clone(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Clean links in a text being composed
+    ///
+    /// Links are found with [`tokenize`], and are replaced
+    /// by their cleaned version.
+    pub fn clean_text(&self, text: &str) -> String {
+        let mut output = String::new();
+        let mut position = 0;
+        for token in tokenize(text, "localhost") {
+            if let LinkKind::Link(link) = token.link {
+                output.push_str(&text[position..token.range.start]);
+                output.push_str(&self.clean_href(&link.href));
+                position = token.range.end;
+            }
+        }
+        output.push_str(&text[position..]);
+        output
+    }
+}
+
+/// Clean the children of a link, if they spell out its href
+///
+/// Like the text of long links, the children are expected to be a hidden prefix,
+/// like `https://`, a visible middle, and an optional ellipsis followed by a hidden
+/// suffix. They are rebuilt from the cleaned href, keeping the length of the prefix
+/// and of the middle. Children with another layout are kept as is.
+fn clean_children(children: &[LinkNode], href: &str, cleaned: &str) -> Vec<LinkNode> {
+    if cleaned == href || full_text(children) != href {
+        return children.to_vec();
+    }
+    let mut prefix = String::new();
+    let mut middle_length = 0;
+    let mut has_suffix = false;
+    for child in children {
+        match child {
+            LinkNode::Hidden(value) if middle_length == 0 => prefix.push_str(value),
+            LinkNode::Text(value) | LinkNode::Highlight(value) if !has_suffix => {
+                middle_length += value.chars().count()
+            }
+            LinkNode::Hidden(_) | LinkNode::Ellipsis => has_suffix = true,
+            _ => return children.to_vec(),
+        }
+    }
+    let rest = match cleaned.strip_prefix(prefix.as_str()) {
+        Some(rest) if middle_length > 0 => rest,
+        _ => return children.to_vec(),
+    };
+
+    let (middle, suffix) = match rest.char_indices().nth(middle_length) {
+        Some((index, _)) if has_suffix => rest.split_at(index),
+        _ => (rest, ""),
+    };
+    let mut children = Vec::new();
+    if !prefix.is_empty() {
+        children.push(LinkNode::Hidden(prefix));
+    }
+    children.push(LinkNode::Text(middle.to_string()));
+    if !suffix.is_empty() {
+        children.push(LinkNode::Ellipsis);
+        children.push(LinkNode::Hidden(suffix.to_string()));
+    }
+    children
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::{display_text, parse_content};
+
+    #[test]
+    fn test_clean_href() {
+        let cleaner = LinkCleaner::with_default_rules();
+        assert_eq!(
+            cleaner.clean_href("https://example.com/a?utm_source=x&id=1&utm_medium=y#top"),
+            "https://example.com/a?id=1#top"
+        );
+        assert_eq!(
+            cleaner.clean_href("https://example.com/a?fbclid=abc"),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            cleaner.clean_href("https://youtu.be/dQw4w9WgXcQ?si=abc&t=42"),
+            "https://youtu.be/dQw4w9WgXcQ?t=42"
+        );
+        assert_eq!(
+            cleaner.clean_href("https://example.com/a?si=abc&t=42"),
+            "https://example.com/a?si=abc&t=42"
+        );
+        assert_eq!(
+            cleaner.clean_href("mailto:a@b.c?utm_source=x"),
+            "mailto:a@b.c?utm_source=x"
+        );
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let mut cleaner = LinkCleaner::new(vec![]);
+        assert_eq!(
+            cleaner.clean_href("https://www.example.com/?utm_source=x&ref=y"),
+            "https://www.example.com/?utm_source=x&ref=y"
+        );
+        cleaner.add_rule(CleanRule::new(
+            vec!["example.com".to_string()],
+            vec!["ref".to_string()],
+        ));
+        assert_eq!(
+            cleaner.clean_href("https://www.example.com/?utm_source=x&ref=y"),
+            "https://www.example.com/?utm_source=x"
+        );
+        assert_eq!(
+            cleaner.clean_href("https://notexample.com/?ref=y"),
+            "https://notexample.com/?ref=y"
+        );
+    }
+
+    #[test]
+    fn test_clean_content() {
+        // 109805244883278164 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_hashtags.html"));
+        let cleaner = LinkCleaner::with_default_rules();
+        assert_eq!(cleaner.clean_content(&content), content);
+
+        let content = vec![vec![ParagraphNode::Link(
+            LinkKind::Link(Link::new(
+                "https://example.com/?utm_campaign=spring".to_string(),
+            )),
+            vec![LinkNode::Text("example.com".to_string())],
+        )]];
+        let expected = vec![vec![ParagraphNode::Link(
+            LinkKind::Link(Link::new("https://example.com/".to_string())),
+            vec![LinkNode::Text("example.com".to_string())],
+        )]];
+        assert_eq!(cleaner.clean_content(&content), expected);
+    }
+
+    #[test]
+    fn test_clean_long_link() {
+        let content = parse_content(concat!(
+            r#"<p><a href="https://example.com/articles/spring?utm_source=mastodon&amp;id=1" "#,
+            r#"rel="nofollow noopener noreferrer" target="_blank">"#,
+            r#"<span class="invisible">https://</span>"#,
+            r#"<span class="ellipsis">example.com/articles/sprin</span>"#,
+            r#"<span class="invisible">g?utm_source=mastodon&amp;id=1</span></a></p>"#
+        ));
+        let cleaner = LinkCleaner::with_default_rules();
+        let cleaned = cleaner.clean_content(&content);
+        let (link, children) = match &cleaned[0][0] {
+            ParagraphNode::Link(LinkKind::Link(link), children) => (link, children),
+            node => panic!("unexpected node {:?}", node),
+        };
+        assert_eq!(link.href, "https://example.com/articles/spring?id=1");
+        assert_eq!(full_text(children), link.href);
+        assert_eq!(display_text(children), "example.com/articles/sprin…");
+    }
+
+    #[test]
+    fn test_clean_long_link_end() {
+        // The removed parameter shares characters with the kept one
+        let content = parse_content(concat!(
+            r#"<p><a href="https://example.com/a?utm_source=aaa&amp;aaa=1" "#,
+            r#"rel="nofollow noopener noreferrer" target="_blank">"#,
+            r#"<span class="invisible">https://</span>"#,
+            r#"<span class="ellipsis">example.com/a?utm_source=aaa&amp;</span>"#,
+            r#"<span class="invisible">aaa=1</span></a></p>"#
+        ));
+        let cleaner = LinkCleaner::with_default_rules();
+        let cleaned = cleaner.clean_content(&content);
+        let children = match &cleaned[0][0] {
+            ParagraphNode::Link(_, children) => children,
+            node => panic!("unexpected node {:?}", node),
+        };
+        assert_eq!(
+            children,
+            &vec![
+                LinkNode::Hidden("https://".to_string()),
+                LinkNode::Text("example.com/a?aaa=1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clean_text() {
+        let cleaner = LinkCleaner::with_default_rules();
+        let text = "Watch https://www.youtube.com/watch?v=abc&si=xyz with #friends?utm_source=x";
+        assert_eq!(
+            cleaner.clean_text(text),
+            "Watch https://www.youtube.com/watch?v=abc with #friends?utm_source=x"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Detect text direction
+//!
+//! Statuses can be written in right-to-left scripts, like arabic or hebrew, and mix
+//! them with left-to-right text. This module offers [`paragraph_direction`], that
+//! detects the direction of a paragraph parsed with
+//! [`parse_content`](super::parse::parse_content), so that it can be displayed
+//! correctly.
+//!
+//! Like the Unicode bidirectional algorithm, the direction is given by the first
+//! strong character of the paragraph. Links, mentions and hashtags are skipped, as
+//! they are usually written in latin script whatever the language of the status.
+
+use super::parse::ParagraphNode;
+use unicode_bidi::{bidi_class, BidiClass};
+
+/// A list specifying directions of text
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Direction {
+    /// Left-to-right
+    Ltr,
+    /// Right-to-left
+    Rtl,
+}
+
+/// Detect the direction of a paragraph
+///
+/// This function returns the direction of the first strong character in the text
+/// of `paragraph`, or `None` if there is no strong character.
+pub fn paragraph_direction(paragraph: &[ParagraphNode]) -> Option<Direction> {
+    paragraph.iter().find_map(|node| match node {
+        ParagraphNode::Text(text) | ParagraphNode::Highlight(text) => text_direction(text),
+        _ => None,
+    })
+}
+
+/// Detect the direction of content
+///
+/// This function returns the direction of each paragraph of `content`.
+pub fn content_directions(content: &[Vec<ParagraphNode>]) -> Vec<Option<Direction>> {
+    content
+        .iter()
+        .map(|paragraph| paragraph_direction(paragraph))
+        .collect()
+}
+
+/// Detect the direction of a text
+///
+/// This function returns the direction of the first strong character
+/// of `text`, or `None` if there is no strong character.
+pub fn text_direction(text: &str) -> Option<Direction> {
+    text.chars().find_map(|c| match bidi_class(c) {
+        BidiClass::L => Some(Direction::Ltr),
+        BidiClass::R | BidiClass::AL => Some(Direction::Rtl),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::{parse_content, LinkNode};
+    use crate::content::{Hashtag, LinkKind};
+
+    #[test]
+    fn test_real_with_mentions() {
+        // 109818097593839444 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_mentions.html"));
+        assert_eq!(
+            content_directions(&content),
+            vec![Some(Direction::Ltr), Some(Direction::Ltr)]
+        );
+    }
+
+    #[test]
+    fn test_text_direction() {
+        assert_eq!(text_direction("Hello"), Some(Direction::Ltr));
+        assert_eq!(text_direction("שלום world"), Some(Direction::Rtl));
+        assert_eq!(text_direction("123 (مرحبا)"), Some(Direction::Rtl));
+        assert_eq!(text_direction("123 !?"), None);
+    }
+
+    #[test]
+    fn test_skip_links() {
+        let content = vec![
+            vec![
+                ParagraphNode::Link(
+                    LinkKind::Hashtag(Hashtag {
+                        href: "https://example.com/tags/rust".to_string(),
+                        tag: "rust".to_string(),
+                    }),
+                    vec![LinkNode::Text("#rust".to_string())],
+                ),
+                ParagraphNode::Text(" مرحبا".to_string()),
+            ],
+            vec![ParagraphNode::Text("😀 ".to_string())],
+        ];
+        assert_eq!(
+            content_directions(&content),
+            vec![Some(Direction::Rtl), None]
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Filter content with keywords
+//!
+//! Mastodon lets users define filters, that hide or warn about statuses containing
+//! some keywords. Filters are applied by the server, but clients also need to apply
+//! them, for example to statuses received while streaming.
+//!
+//! This module offers [`Filters`], that evaluates keyword filters against content
+//! parsed with [`parse_content`](super::parse::parse_content), using the same
+//! semantics as Mastodon:
+//!
+//! - Keywords are matched case-insensitively.
+//! - Whole-word keywords only match at word boundaries. Like Mastodon, a boundary is only
+//!   required on sides where the keyword starts or ends with a word character, so
+//!   `#rust` still matches in `I like #rust`.
+//!
+//! Hidden parts of links are not matched, and hashtags are matched using their tag.
+
+use super::parse::{display_text, ParagraphNode};
+use super::LinkKind;
+use regex::{Regex, RegexBuilder};
+
+/// A list specifying contexts where filters apply
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum FilterContext {
+    /// Home timeline and lists
+    Home,
+    /// Notifications
+    Notifications,
+    /// Public timelines
+    Public,
+    /// Threads
+    Thread,
+    /// Account profiles
+    Account,
+}
+
+/// A filter keyword
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct FilterKeyword {
+    /// Keyword
+    pub keyword: String,
+    /// Whether the keyword should only match whole words
+    pub whole_word: bool,
+}
+
+impl FilterKeyword {
+    /// Create a filter keyword
+    pub fn new(keyword: String, whole_word: bool) -> Self {
+        FilterKeyword {
+            keyword,
+            whole_word,
+        }
+    }
+}
+
+/// A filter
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Filter {
+    /// Title
+    ///
+    /// Displayed to the user when a status is filtered.
+    pub title: String,
+    /// Contexts where this filter applies
+    pub context: Vec<FilterContext>,
+    /// Keywords
+    pub keywords: Vec<FilterKeyword>,
+}
+
+impl Filter {
+    /// Create a filter
+    pub fn new(title: String, context: Vec<FilterContext>, keywords: Vec<FilterKeyword>) -> Self {
+        Filter {
+            title,
+            context,
+            keywords,
+        }
+    }
+}
+
+/// A filter match
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct FilterMatch<'a> {
+    /// Filter that matched
+    pub filter: &'a Filter,
+    /// Keywords of the filter that matched
+    pub keywords: Vec<&'a FilterKeyword>,
+}
+
+/// A list of compiled filters
+#[derive(Clone, Debug)]
+pub struct Filters {
+    filters: Vec<(Filter, Vec<Regex>)>,
+}
+
+impl Filters {
+    /// Compile filters
+    pub fn new(filters: Vec<Filter>) -> Self {
+        let filters = filters
+            .into_iter()
+            .map(|filter| {
+                let regexes = filter.keywords.iter().map(compile_keyword).collect();
+                (filter, regexes)
+            })
+            .collect();
+        Filters { filters }
+    }
+
+    /// Apply filters
+    ///
+    /// This method returns the filters matching a status in the given `context`. The
+    /// status is described by its parsed `content` and its `spoiler_text`, that can be
+    /// empty.
+    ///
+    /// Filters are returned in the order they were provided in.
+    pub fn apply(
+        &self,
+        context: FilterContext,
+        content: &[Vec<ParagraphNode>],
+        spoiler_text: &str,
+    ) -> Vec<FilterMatch<'_>> {
+        let text = searchable_text(content, spoiler_text);
+        self.filters
+            .iter()
+            .filter(|(filter, _)| filter.context.contains(&context))
+            .filter_map(|(filter, regexes)| {
+                let keywords = filter
+                    .keywords
+                    .iter()
+                    .zip(regexes.iter())
+                    .filter(|(_, regex)| regex.is_match(&text))
+                    .map(|(keyword, _)| keyword)
+                    .collect::<Vec<_>>();
+                if keywords.is_empty() {
+                    None
+                } else {
+                    Some(FilterMatch { filter, keywords })
+                }
+            })
+            .collect()
+    }
+}
+
+fn compile_keyword(keyword: &FilterKeyword) -> Regex {
+    let mut pattern = regex::escape(&keyword.keyword);
+    if keyword.whole_word {
+        if keyword.keyword.starts_with(is_word_char) {
+            pattern = format!(r"\b{}", pattern);
+        }
+        if keyword.keyword.ends_with(is_word_char) {
+            pattern = format!(r"{}\b", pattern);
+        }
+    }
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .unwrap()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn searchable_text(content: &[Vec<ParagraphNode>], spoiler_text: &str) -> String {
+    let mut paragraphs = Vec::new();
+    if !spoiler_text.is_empty() {
+        paragraphs.push(spoiler_text.to_string());
+    }
+    for paragraph in content {
+        let mut text = String::new();
+        for node in paragraph {
+            match node {
+                ParagraphNode::Text(value) | ParagraphNode::Highlight(value) => {
+                    text.push_str(value)
+                }
+                ParagraphNode::NewLine => text.push('\n'),
+                ParagraphNode::Link(LinkKind::Hashtag(hashtag), _) => {
+                    text.push('#');
+                    text.push_str(hashtag.tag());
+                }
+                ParagraphNode::Link(_, children) => text.push_str(&display_text(children)),
+                ParagraphNode::Ellipsis => {}
+            }
+        }
+        paragraphs.push(text);
+    }
+    paragraphs.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::parse_content;
+
+    fn filter(title: &str, keywords: &[(&str, bool)]) -> Filter {
+        Filter::new(
+            title.to_string(),
+            vec![FilterContext::Home, FilterContext::Public],
+            keywords
+                .iter()
+                .map(|(keyword, whole_word)| FilterKeyword::new(keyword.to_string(), *whole_word))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_whole_word() {
+        // 109818097593839444 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_mentions.html"));
+        let filters = Filters::new(vec![
+            filter("Stick", &[("stick", true)]),
+            filter("Sticker", &[("STICKER", false)]),
+            filter("Basic", &[("basic", false)]),
+            filter("Question", &[("?", true)]),
+        ]);
+        let titles = filters
+            .apply(FilterContext::Home, &content, "")
+            .into_iter()
+            .map(|filter_match| filter_match.filter.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["Sticker", "Basic", "Question"]);
+    }
+
+    #[test]
+    fn test_hashtags_and_links() {
+        // 109805244883278164 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_hashtags.html"));
+        let filters = Filters::new(vec![
+            filter("Hashtag", &[("#ttrpg", true)]),
+            filter("Tag", &[("magic", true)]),
+            filter(
+                "Hidden",
+                &[("www.reddit", false), ("oc_magic_coding", false)],
+            ),
+            filter("Visible", &[("reddit.com", false)]),
+        ]);
+        let matches = filters.apply(FilterContext::Public, &content, "");
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0].filter.title, "Hashtag");
+        assert_eq!(matches[1].filter.title, "Tag");
+        assert_eq!(matches[2].filter.title, "Visible");
+        assert_eq!(
+            matches[2].keywords,
+            vec![&FilterKeyword::new("reddit.com".to_string(), false)]
+        );
+    }
+
+    #[test]
+    fn test_non_ascii_hashtag() {
+        let content = parse_content(concat!(
+            r#"<p>Un <a href="https://mastodon.social/tags/caf%C3%A9" "#,
+            r#"class="mention hashtag" rel="tag">#<span>café</span></a></p>"#
+        ));
+        let filters = Filters::new(vec![
+            filter("Hashtag", &[("#café", true)]),
+            filter("Word", &[("café", true)]),
+            filter("Encoded", &[("%C3%A9", false)]),
+        ]);
+        let titles = filters
+            .apply(FilterContext::Home, &content, "")
+            .into_iter()
+            .map(|filter_match| filter_match.filter.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["Hashtag", "Word"]);
+    }
+
+    #[test]
+    fn test_context_and_spoiler() {
+        // 109882001535463183 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_newlines.html"));
+        let filters = Filters::new(vec![filter("Spoiler", &[("politics", true)])]);
+        assert_eq!(
+            filters
+                .apply(FilterContext::Home, &content, "US Politics")
+                .len(),
+            1
+        );
+        assert!(filters
+            .apply(FilterContext::Thread, &content, "US Politics")
+            .is_empty());
+        assert!(filters.apply(FilterContext::Home, &content, "").is_empty());
+    }
+}
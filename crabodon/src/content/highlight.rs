@@ -0,0 +1,269 @@
+//! Highlight search terms
+//!
+//! This module offers [`highlight_content`], that highlights a search query in content
+//! parsed with [`parse_content`](super::parse::parse_content).
+//!
+//! Matching is case-insensitive and diacritic-insensitive, so that `cafe` matches `Café`.
+//! Matches are searched in the whole text of a paragraph, including text inside links,
+//! so that a match can span several nodes. Matching text is wrapped in
+//! [`ParagraphNode::Highlight`] or [`LinkNode::Highlight`] nodes, splitting text nodes
+//! as needed.
+
+use super::parse::{LinkNode, ParagraphNode};
+use std::ops::Range;
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+/// Highlight a query
+///
+/// This function returns a copy of `content` where text matching `query`
+/// is highlighted.
+pub fn highlight_content(content: &[Vec<ParagraphNode>], query: &str) -> Vec<Vec<ParagraphNode>> {
+    let query = normalize(query);
+    if query.is_empty() {
+        return content.to_vec();
+    }
+
+    content
+        .iter()
+        .map(|paragraph| highlight_paragraph(paragraph, &query))
+        .collect()
+}
+
+/// A normalized character
+///
+/// Maps a normalized character to the text node it comes from,
+/// and to its range in this text node. Separators between text
+/// that is not contiguous have no character.
+struct Normalized {
+    c: Option<char>,
+    source: Option<(usize, Range<usize>)>,
+}
+
+fn highlight_paragraph(paragraph: &[ParagraphNode], query: &[char]) -> Vec<ParagraphNode> {
+    let texts = collect_texts(paragraph);
+    let mut haystack = Vec::new();
+    for (index, text) in texts.iter().enumerate() {
+        match text {
+            Some(text) => push_normalized(&mut haystack, index, text),
+            None => haystack.push(Normalized {
+                c: None,
+                source: None,
+            }),
+        }
+    }
+
+    let mut highlights = vec![Vec::new(); texts.len()];
+    let mut position = 0;
+    while position + query.len() <= haystack.len() {
+        let candidate = &haystack[position..position + query.len()];
+        let is_match = candidate
+            .iter()
+            .zip(query.iter())
+            .all(|(normalized, c)| normalized.c == Some(*c));
+        if !is_match {
+            position += 1;
+            continue;
+        }
+
+        for normalized in candidate {
+            if let Some((index, range)) = &normalized.source {
+                add_range(&mut highlights[*index], range.clone());
+            }
+        }
+        position += query.len();
+    }
+
+    rebuild_paragraph(paragraph, &highlights)
+}
+
+/// Collect text nodes
+///
+/// Text nodes are returned in order, with `None` separating
+/// text that is not contiguous.
+fn collect_texts(paragraph: &[ParagraphNode]) -> Vec<Option<&str>> {
+    let mut texts = Vec::new();
+    for node in paragraph {
+        match node {
+            ParagraphNode::Text(text) => texts.push(Some(text.as_str())),
+            ParagraphNode::Link(_, children) => {
+                for child in children {
+                    match child {
+                        LinkNode::Text(text) => texts.push(Some(text.as_str())),
+                        _ => texts.push(None),
+                    }
+                }
+            }
+            _ => texts.push(None),
+        }
+    }
+    texts
+}
+
+fn rebuild_paragraph(
+    paragraph: &[ParagraphNode],
+    highlights: &[Vec<Range<usize>>],
+) -> Vec<ParagraphNode> {
+    // Highlights are in the same order as the nodes returned by collect_texts
+    let mut highlights = highlights.iter();
+    let mut output = Vec::new();
+    for node in paragraph {
+        match node {
+            ParagraphNode::Text(text) => {
+                let ranges = highlights.next().unwrap();
+                for (is_highlighted, text) in split_text(text, ranges) {
+                    if is_highlighted {
+                        output.push(ParagraphNode::Highlight(text));
+                    } else {
+                        output.push(ParagraphNode::Text(text));
+                    }
+                }
+            }
+            ParagraphNode::Link(link, children) => {
+                let mut new_children = Vec::new();
+                for child in children {
+                    let ranges = highlights.next().unwrap();
+                    match child {
+                        LinkNode::Text(text) => {
+                            for (is_highlighted, text) in split_text(text, ranges) {
+                                if is_highlighted {
+                                    new_children.push(LinkNode::Highlight(text));
+                                } else {
+                                    new_children.push(LinkNode::Text(text));
+                                }
+                            }
+                        }
+                        child => new_children.push(child.clone()),
+                    }
+                }
+                output.push(ParagraphNode::Link(link.clone(), new_children));
+            }
+            node => {
+                highlights.next();
+                output.push(node.clone());
+            }
+        }
+    }
+    output
+}
+
+fn split_text(text: &str, ranges: &[Range<usize>]) -> Vec<(bool, String)> {
+    let mut output = Vec::new();
+    let mut position = 0;
+    for range in ranges {
+        if range.start > position {
+            output.push((false, text[position..range.start].to_string()));
+        }
+        output.push((true, text[range.clone()].to_string()));
+        position = range.end;
+    }
+    if position < text.len() {
+        output.push((false, text[position..].to_string()));
+    }
+    output
+}
+
+fn add_range(ranges: &mut Vec<Range<usize>>, range: Range<usize>) {
+    match ranges.last_mut() {
+        Some(last) if last.end >= range.start => last.end = last.end.max(range.end),
+        _ => ranges.push(range),
+    }
+}
+
+fn push_normalized(haystack: &mut Vec<Normalized>, index: usize, text: &str) {
+    for (offset, c) in text.char_indices() {
+        let range = offset..offset + c.len_utf8();
+        for c in normalize_char(c) {
+            haystack.push(Normalized {
+                c: Some(c),
+                source: Some((index, range.clone())),
+            });
+        }
+    }
+}
+
+fn normalize(text: &str) -> Vec<char> {
+    text.chars().flat_map(normalize_char).collect()
+}
+
+fn normalize_char(c: char) -> Vec<char> {
+    let mut output = Vec::new();
+    decompose_canonical(c, |c| {
+        if !is_combining_mark(c) {
+            output.extend(c.to_lowercase());
+        }
+    });
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::parse_content;
+    use crate::content::{Hashtag, LinkKind};
+
+    #[test]
+    fn test_highlight_text() {
+        // 109882001535463183 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_newlines.html"));
+        let expected = vec![vec![
+            ParagraphNode::Highlight("Test".to_string()),
+            ParagraphNode::Text(" 1 please ignore".to_string()),
+            ParagraphNode::NewLine,
+            ParagraphNode::Highlight("Test".to_string()),
+            ParagraphNode::Text(" 1 (cont)".to_string()),
+        ]];
+        assert_eq!(highlight_content(&content, "tEST"), expected);
+    }
+
+    #[test]
+    fn test_highlight_diacritics() {
+        let content = vec![vec![ParagraphNode::Text("Un café crème".to_string())]];
+        let expected = vec![vec![
+            ParagraphNode::Text("Un ".to_string()),
+            ParagraphNode::Highlight("café".to_string()),
+            ParagraphNode::Text(" crème".to_string()),
+        ]];
+        assert_eq!(highlight_content(&content, "CAFE"), expected);
+        assert_eq!(highlight_content(&content, "cafè"), expected);
+    }
+
+    #[test]
+    fn test_highlight_across_nodes() {
+        // 109805244883278164 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_hashtags.html"));
+        let highlighted = highlight_content(&content, "rpg #mag");
+        let expected = [
+            ParagraphNode::Link(
+                LinkKind::Hashtag(Hashtag {
+                    href: "https://dice.camp/tags/ttrpg".to_string(),
+                    tag: "ttrpg".to_string(),
+                }),
+                vec![
+                    LinkNode::Text("#tt".to_string()),
+                    LinkNode::Highlight("rpg".to_string()),
+                ],
+            ),
+            ParagraphNode::Highlight(" ".to_string()),
+            ParagraphNode::Link(
+                LinkKind::Hashtag(Hashtag {
+                    href: "https://dice.camp/tags/magic".to_string(),
+                    tag: "magic".to_string(),
+                }),
+                vec![
+                    LinkNode::Highlight("#mag".to_string()),
+                    LinkNode::Text("ic".to_string()),
+                ],
+            ),
+        ];
+        assert_eq!(highlighted[0], content[0]);
+        assert_eq!(highlighted[2][..3], expected[..]);
+    }
+
+    #[test]
+    fn test_highlight_nothing() {
+        // 109882001535463183 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_newlines.html"));
+        assert_eq!(highlight_content(&content, ""), content);
+        assert_eq!(highlight_content(&content, "ignore\ntest"), content);
+    }
+}
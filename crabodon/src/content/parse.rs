@@ -10,10 +10,15 @@
 //! - A paragraph node can be a link, a text or a new line
 //! - A link (link, mention or hashtag) contain a list of [`LinkNode`]
 //! - A link node can be a text or a new line
+//!
+//! Mastodon hides parts of long links. Those parts are kept as [`LinkNode::Hidden`]
+//! nodes, so that both the text displayed by Mastodon, see [`display_text`], and
+//! the full text of the link, see [`full_text`], are available.
 
 use super::visit;
 use super::LinkKind;
 use std::mem;
+use url::Url;
 
 /// A paragraph node
 ///
@@ -28,6 +33,18 @@ pub enum ParagraphNode {
     Text(String),
     /// A new line
     NewLine,
+    /// An ellipsis
+    ///
+    /// Marks the place where content was truncated, see
+    /// [`truncate`](super::truncate). This node is never
+    /// produced by [`parse_content`].
+    Ellipsis,
+    /// A highlighted text
+    ///
+    /// Marks text matching a search, see
+    /// [`highlight`](super::highlight). This node is never
+    /// produced by [`parse_content`].
+    Highlight(String),
 }
 
 /// A link node
@@ -41,16 +58,76 @@ pub enum LinkNode {
     Text(String),
     /// A new line
     NewLine,
+    /// A hidden text
+    ///
+    /// Part of a long link that is not displayed, like
+    /// its protocol.
+    Hidden(String),
+    /// An ellipsis
+    ///
+    /// Marks the end of the displayed part of a long link.
+    Ellipsis,
+    /// A highlighted text
+    ///
+    /// Marks text matching a search, see
+    /// [`highlight`](super::highlight). This node is never
+    /// produced by [`parse_content`].
+    Highlight(String),
+}
+
+/// Text displayed for a link
+///
+/// This function returns the text of a link, as displayed by Mastodon.
+/// Hidden parts are skipped, and an ellipsis is displayed as `…`.
+pub fn display_text(children: &[LinkNode]) -> String {
+    let mut text = String::new();
+    for child in children {
+        match child {
+            LinkNode::Text(value) | LinkNode::Highlight(value) => text.push_str(value),
+            LinkNode::NewLine => text.push('\n'),
+            LinkNode::Ellipsis => text.push('…'),
+            LinkNode::Hidden(_) => {}
+        }
+    }
+    text
+}
+
+/// Full text of a link
+///
+/// This function returns the whole text of a link, including hidden parts.
+/// For links that are not mentions or hashtags, it is usually the full URL.
+pub fn full_text(children: &[LinkNode]) -> String {
+    let mut text = String::new();
+    for child in children {
+        match child {
+            LinkNode::Text(value) | LinkNode::Highlight(value) | LinkNode::Hidden(value) => {
+                text.push_str(value)
+            }
+            LinkNode::NewLine => text.push('\n'),
+            LinkNode::Ellipsis => {}
+        }
+    }
+    text
 }
 
 /// Parse content
 ///
 /// This function extracts information from Mastodon content
 /// as list of paragraphs. Each paragraph is a list of paragraph nodes.
+///
+/// Relative links are not resolved, see [`parse_content_with_base`].
 pub fn parse_content(content: &str) -> Vec<Vec<ParagraphNode>> {
     visit::visit_content(content, ParseVisitor::default())
 }
 
+/// Parse content, resolving links against a base URL
+///
+/// This function behaves like [`parse_content`], but relative
+/// links are resolved against `base`, usually the URL of the status.
+pub fn parse_content_with_base(content: &str, base: &Url) -> Vec<Vec<ParagraphNode>> {
+    visit::visit_content_with_base(content, base, ParseVisitor::default())
+}
+
 #[derive(Default)]
 struct ParseVisitor {
     paragraphs: Vec<Vec<ParagraphNode>>,
@@ -83,6 +160,23 @@ impl visit::Visit for ParseVisitor {
         }
     }
 
+    fn hidden_text(&mut self, text: String) {
+        if self.paragraph_count > 0 && self.link_count > 0 {
+            self.current_link.push(LinkNode::Hidden(text))
+        }
+    }
+
+    fn ellipsis(&mut self) {
+        if self.paragraph_count > 0 {
+            if self.link_count > 0 {
+                self.current_link.push(LinkNode::Ellipsis)
+            } else {
+                self.current_paragraph
+                    .push(ParagraphNode::Text("…".to_string()))
+            }
+        }
+    }
+
     fn begin_paragraph(&mut self) {
         self.paragraph_count += 1;
     }
@@ -116,7 +210,7 @@ impl visit::Visit for ParseVisitor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::content::{Hashtag, Link, Mention};
+    use crate::content::{Hashtag, Link, LinkScheme, Mention};
 
     #[test]
     fn test_real_with_hashtags() {
@@ -130,13 +224,16 @@ mod tests {
             vec![
                 ParagraphNode::Text("[original source: ".to_string()),
                 ParagraphNode::Link(
-                    LinkKind::Link(Link {
-                        href: "https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/"
+                    LinkKind::Link(Link::new(
+                        "https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/"
                             .to_string(),
-                    }),
-                    vec![LinkNode::Text(
-                        "reddit.com/r/comics/comments/1…".to_string(),
-                    )],
+                    )),
+                    vec![
+                        LinkNode::Hidden("https://www.".to_string()),
+                        LinkNode::Text("reddit.com/r/comics/comments/1".to_string()),
+                        LinkNode::Ellipsis,
+                        LinkNode::Hidden("0rukp8/oc_magic_coding/".to_string()),
+                    ],
                 ),
                 ParagraphNode::Text("]".to_string()),
             ],
@@ -215,4 +312,65 @@ mod tests {
         ]];
         assert_eq!(parse_content(content), expected);
     }
+
+    #[test]
+    fn test_link_schemes() {
+        let content = concat!(
+            r#"<p><a href="javascript:alert(1)">a</a> <a href="data:text/html,x">b</a> "#,
+            r#"<a href="mailto:user@example.com">c</a> <a href="/about">d</a></p>"#
+        );
+        let base = Url::parse("https://mastodon.social/@user/109882001535463183").unwrap();
+        let links = parse_content_with_base(content, &base)
+            .into_iter()
+            .flatten()
+            .filter_map(|node| match node {
+                ParagraphNode::Link(LinkKind::Link(link), _) => Some(link),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let schemes = links.iter().map(|link| link.scheme).collect::<Vec<_>>();
+        assert_eq!(
+            schemes,
+            vec![
+                LinkScheme::Unsafe,
+                LinkScheme::Unsafe,
+                LinkScheme::Mailto,
+                LinkScheme::Relative
+            ]
+        );
+        assert_eq!(links[0].url, None);
+        assert_eq!(links[1].url, None);
+        assert_eq!(
+            links[3].url.as_ref().map(Url::as_str),
+            Some("https://mastodon.social/about")
+        );
+    }
+
+    #[test]
+    fn test_relative_link_without_base() {
+        let content = r#"<p><a href="/about">about</a></p>"#;
+        let link = match &parse_content(content)[0][0] {
+            ParagraphNode::Link(LinkKind::Link(link), _) => link.clone(),
+            node => panic!("unexpected node {:?}", node),
+        };
+        assert_eq!(link.scheme, LinkScheme::Relative);
+        assert_eq!(link.url, None);
+    }
+
+    #[test]
+    fn test_link_text() {
+        // 109805244883278164 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_hashtags.html"));
+        let (link, hashtag) = match (&content[1][1], &content[2][0]) {
+            (ParagraphNode::Link(_, link), ParagraphNode::Link(_, hashtag)) => (link, hashtag),
+            nodes => panic!("unexpected nodes {:?}", nodes),
+        };
+        assert_eq!(display_text(link), "reddit.com/r/comics/comments/1…");
+        assert_eq!(
+            full_text(link),
+            "https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/"
+        );
+        assert_eq!(display_text(hashtag), "#ttrpg");
+        assert_eq!(full_text(hashtag), "#ttrpg");
+    }
 }
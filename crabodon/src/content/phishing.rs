@@ -0,0 +1,295 @@
+//! Detect suspicious links
+//!
+//! Links whose text looks like a domain, but that point to another domain, are a
+//! common scam pattern. So are links to domains using characters that look like
+//! latin letters, like the cyrillic `а`, known as IDN homograph attacks.
+//!
+//! This module offers [`check_link`], that checks a single link, and
+//! [`find_suspicious_links`], that checks all links in content parsed with
+//! [`parse_content`](super::parse::parse_content). Mentions and hashtags are
+//! not checked.
+
+use super::parse::{display_text, ParagraphNode};
+use super::{Link, LinkKind};
+use url::Url;
+
+/// A list specifying reasons for a link to be suspicious
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum LinkWarning {
+    /// The text of the link is a domain that is not the domain of the link
+    Mismatch {
+        /// Domain displayed in the text of the link
+        displayed_host: String,
+        /// Domain the link points to
+        host: String,
+    },
+    /// The domain of the link is an IDN homograph
+    Homograph {
+        /// Domain the link points to, in Unicode form
+        host: String,
+    },
+}
+
+/// A suspicious link
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct SuspiciousLink {
+    /// Link
+    pub link: Link,
+    /// Text of the link
+    pub text: String,
+    /// Reasons for this link to be suspicious
+    pub warnings: Vec<LinkWarning>,
+}
+
+/// Check a link
+///
+/// This function checks if `link`, displayed with `text`, is suspicious. It returns
+/// a list of warnings, that is empty if the link is not suspicious.
+///
+/// Text that does not look like a URL or a domain is not compared to the link. A link
+/// to a subdomain of the displayed domain is not considered as a mismatch.
+pub fn check_link(link: &Link, text: &str) -> Vec<LinkWarning> {
+    let mut warnings = Vec::new();
+    let host = match link.url.as_ref().and_then(|url| url.host_str()) {
+        Some(host) => normalize_host(host),
+        None => return warnings,
+    };
+
+    if let Some(displayed_host) = displayed_host(text) {
+        let is_same = host == displayed_host || host.ends_with(&format!(".{}", displayed_host));
+        if !is_same {
+            warnings.push(LinkWarning::Mismatch {
+                displayed_host: domain_to_unicode(&displayed_host),
+                host: domain_to_unicode(&host),
+            });
+        }
+    }
+
+    let unicode_host = domain_to_unicode(&host);
+    if unicode_host.split('.').any(is_homograph) {
+        warnings.push(LinkWarning::Homograph { host: unicode_host });
+    }
+    warnings
+}
+
+/// Decode the punycode labels of a domain
+///
+/// Labels that cannot be decoded are kept as is.
+fn domain_to_unicode(domain: &str) -> String {
+    idna::domain_to_unicode(domain).0
+}
+
+/// Find suspicious links
+///
+/// This function checks all links in `content` with [`check_link`].
+pub fn find_suspicious_links(content: &[Vec<ParagraphNode>]) -> Vec<SuspiciousLink> {
+    content
+        .iter()
+        .flatten()
+        .filter_map(|node| match node {
+            ParagraphNode::Link(LinkKind::Link(link), children) => {
+                let text = display_text(children);
+                let warnings = check_link(link, &text);
+                if warnings.is_empty() {
+                    None
+                } else {
+                    Some(SuspiciousLink {
+                        link: link.clone(),
+                        text,
+                        warnings,
+                    })
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn normalize_host(host: &str) -> String {
+    let host = host.trim_end_matches('.').to_lowercase();
+    match host.strip_prefix("www.") {
+        Some(host) => host.to_string(),
+        None => host,
+    }
+}
+
+/// Find the host displayed in a text
+///
+/// Mastodon displays links without their protocol, and ends truncated
+/// links with an ellipsis.
+fn displayed_host(text: &str) -> Option<String> {
+    let text = text.trim().trim_end_matches('…');
+    if text.is_empty() || text.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let url = if text.contains("://") {
+        Url::parse(text).ok()?
+    } else {
+        let separators: &[char] = &['/', '?', '#'];
+        let host = text.split(separators).next()?;
+        if !looks_like_domain(host) {
+            return None;
+        }
+        Url::parse(&format!("http://{}", text)).ok()?
+    };
+    url.host_str().map(normalize_host)
+}
+
+fn looks_like_domain(host: &str) -> bool {
+    let host = host.split(':').next().unwrap_or("");
+    let labels = host.split('.').collect::<Vec<_>>();
+    let tld = labels.last().copied().unwrap_or("");
+    labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+        && tld.chars().count() >= 2
+        && tld.chars().all(char::is_alphabetic)
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Other,
+}
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        'a'..='z' | 'A'..='Z' | '\u{00c0}'..='\u{024f}' | '\u{1e00}'..='\u{1eff}' => {
+            Some(Script::Latin)
+        }
+        '\u{0400}'..='\u{052f}' => Some(Script::Cyrillic),
+        '\u{0370}'..='\u{03ff}' | '\u{1f00}'..='\u{1fff}' => Some(Script::Greek),
+        c if c.is_alphabetic() => Some(Script::Other),
+        _ => None,
+    }
+}
+
+/// Cyrillic and greek letters that look like latin letters
+///
+/// Hosts are always lowercase, so only lowercase letters are listed.
+const CONFUSABLES: &str = "асԁеһіјкӏмопрԛѕтуѵԝхьгαικνοπρτυχϲϳ";
+
+/// Check if a domain label is an IDN homograph
+///
+/// A label is an homograph if it mixes latin, cyrillic or greek scripts,
+/// or if it only contains cyrillic or greek letters that look like
+/// latin letters.
+fn is_homograph(label: &str) -> bool {
+    if label.is_ascii() {
+        return false;
+    }
+
+    let mut scripts = label.chars().filter_map(script).collect::<Vec<_>>();
+    scripts.dedup();
+    let has_latin = scripts.contains(&Script::Latin);
+    let has_cyrillic = scripts.contains(&Script::Cyrillic);
+    let has_greek = scripts.contains(&Script::Greek);
+    let mixed = [has_latin, has_cyrillic, has_greek]
+        .iter()
+        .filter(|&&has| has)
+        .count()
+        > 1;
+    if mixed {
+        return true;
+    }
+
+    (has_cyrillic || has_greek)
+        && label
+            .chars()
+            .filter(|c| c.is_alphabetic())
+            .all(|c| CONFUSABLES.contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::{parse_content, LinkNode};
+
+    fn link(href: &str) -> Link {
+        Link::new(href.to_string())
+    }
+
+    #[test]
+    fn test_real_with_hashtags() {
+        // 109805244883278164 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_hashtags.html"));
+        assert_eq!(find_suspicious_links(&content), vec![]);
+    }
+
+    #[test]
+    fn test_mismatch() {
+        assert_eq!(
+            check_link(&link("https://example.com/path"), "click here"),
+            vec![]
+        );
+        assert_eq!(
+            check_link(&link("https://example.com/path"), "example.com"),
+            vec![]
+        );
+        assert_eq!(
+            check_link(&link("https://login.example.com"), "example.com"),
+            vec![]
+        );
+        assert_eq!(
+            check_link(&link("https://evil.com/login"), "https://paypal.com/login"),
+            vec![LinkWarning::Mismatch {
+                displayed_host: "paypal.com".to_string(),
+                host: "evil.com".to_string(),
+            }]
+        );
+        assert_eq!(
+            check_link(&link("https://paypal.com.evil.com"), "PayPal.com"),
+            vec![LinkWarning::Mismatch {
+                displayed_host: "paypal.com".to_string(),
+                host: "paypal.com.evil.com".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_homograph() {
+        assert_eq!(
+            check_link(&link("https://xn--80ak6aa92e.com/"), "click here"),
+            vec![LinkWarning::Homograph {
+                host: "аррӏе.com".to_string()
+            }]
+        );
+        assert_eq!(
+            check_link(&link("https://pаypal.com/"), "click here"),
+            vec![LinkWarning::Homograph {
+                host: "pаypal.com".to_string()
+            }]
+        );
+        assert_eq!(
+            check_link(&link("https://пример.рф/"), "click here"),
+            vec![]
+        );
+        assert_eq!(check_link(&link("https://café.fr/"), "café.fr"), vec![]);
+    }
+
+    #[test]
+    fn test_find_suspicious_links() {
+        let content = vec![vec![
+            ParagraphNode::Text("Log in at ".to_string()),
+            ParagraphNode::Link(
+                LinkKind::Link(link("https://evil.com/")),
+                vec![LinkNode::Text("bank.com".to_string())],
+            ),
+        ]];
+        let expected = vec![SuspiciousLink {
+            link: link("https://evil.com/"),
+            text: "bank.com".to_string(),
+            warnings: vec![LinkWarning::Mismatch {
+                displayed_host: "bank.com".to_string(),
+                host: "evil.com".to_string(),
+            }],
+        }];
+        assert_eq!(find_suspicious_links(&content), expected);
+    }
+}
@@ -0,0 +1,225 @@
+//! Truncate parsed content
+//!
+//! This module offers [`truncate_content`], that truncates content parsed with
+//! [`parse_content`](super::parse::parse_content) to a given number of characters,
+//! while preserving its structure.
+//!
+//! Links, mentions and hashtags are never cut: they are either kept whole, or
+//! removed. Text is cut at a grapheme boundary, preferably at a word boundary.
+//! An [`Ellipsis`](ParagraphNode::Ellipsis) node is appended where the content
+//! was cut.
+
+use super::parse::{display_text, ParagraphNode};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Truncated content
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Truncated {
+    /// Content
+    ///
+    /// Truncated content, as a list of paragraphs.
+    pub content: Vec<Vec<ParagraphNode>>,
+    /// Whether the content was truncated
+    ///
+    /// When true, something was cut, and a "Show more"
+    /// action can be displayed.
+    pub is_truncated: bool,
+}
+
+/// Truncate content
+///
+/// This function keeps at most `length` characters of `content`. Characters are
+/// grapheme clusters. Links count for the length of their text, and new lines
+/// count as 1 character.
+pub fn truncate_content(content: &[Vec<ParagraphNode>], length: usize) -> Truncated {
+    let mut remaining = length;
+    let mut output = Vec::new();
+    for paragraph in content {
+        let mut nodes = Vec::new();
+        for node in paragraph {
+            let node_length = node_length(node);
+            if node_length <= remaining {
+                remaining -= node_length;
+                nodes.push(node.clone());
+                continue;
+            }
+
+            if let ParagraphNode::Text(text) = node {
+                let text = truncate_text(text, remaining);
+                if !text.is_empty() {
+                    nodes.push(ParagraphNode::Text(text));
+                }
+            }
+            trim_end(&mut nodes);
+            if nodes.is_empty() {
+                // Nothing of this paragraph was kept, so the previous one is cut
+                if let Some(previous) = output.pop() {
+                    nodes = previous;
+                    trim_end(&mut nodes);
+                }
+            }
+            nodes.push(ParagraphNode::Ellipsis);
+            output.push(nodes);
+            return Truncated {
+                content: output,
+                is_truncated: true,
+            };
+        }
+        output.push(nodes);
+    }
+
+    Truncated {
+        content: output,
+        is_truncated: false,
+    }
+}
+
+fn node_length(node: &ParagraphNode) -> usize {
+    match node {
+        ParagraphNode::Link(_, children) => display_text(children).graphemes(true).count(),
+        ParagraphNode::Text(text) | ParagraphNode::Highlight(text) => text.graphemes(true).count(),
+        _ => 1,
+    }
+}
+
+fn truncate_text(text: &str, length: usize) -> String {
+    let end = text
+        .grapheme_indices(true)
+        .nth(length)
+        .map(|(index, _)| index)
+        .unwrap_or_else(|| text.len());
+    let truncated = &text[..end];
+
+    // Prefer cutting at a word boundary, unless it is the only word
+    let is_word_cut = !text[end..].starts_with(char::is_whitespace);
+    match truncated.rfind(char::is_whitespace) {
+        Some(index) if is_word_cut => truncated[..index].to_string(),
+        _ => truncated.to_string(),
+    }
+}
+
+fn trim_end(nodes: &mut Vec<ParagraphNode>) {
+    while let Some(node) = nodes.pop() {
+        match node {
+            ParagraphNode::Text(text) => {
+                let text = text.trim_end();
+                if !text.is_empty() {
+                    nodes.push(ParagraphNode::Text(text.to_string()));
+                    return;
+                }
+            }
+            ParagraphNode::NewLine => {}
+            node => {
+                nodes.push(node);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::{parse_content, LinkNode};
+    use crate::content::{Link, LinkKind, Mention};
+
+    #[test]
+    fn test_not_truncated() {
+        // 109882001535463183 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_newlines.html"));
+        let expected = Truncated {
+            content: content.clone(),
+            is_truncated: false,
+        };
+        assert_eq!(truncate_content(&content, 34), expected);
+    }
+
+    #[test]
+    fn test_truncate_text() {
+        // 109882001535463183 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_newlines.html"));
+        let expected = Truncated {
+            content: vec![vec![
+                ParagraphNode::Text("Test 1 please ignore".to_string()),
+                ParagraphNode::NewLine,
+                ParagraphNode::Text("Test 1".to_string()),
+                ParagraphNode::Ellipsis,
+            ]],
+            is_truncated: true,
+        };
+        assert_eq!(truncate_content(&content, 29), expected);
+
+        let content = vec![vec![ParagraphNode::Text("Unbreakable".to_string())]];
+        let expected = Truncated {
+            content: vec![vec![
+                ParagraphNode::Text("Unbr".to_string()),
+                ParagraphNode::Ellipsis,
+            ]],
+            is_truncated: true,
+        };
+        assert_eq!(truncate_content(&content, 4), expected);
+    }
+
+    #[test]
+    fn test_truncate_links() {
+        // 109805244883278164 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_hashtags.html"));
+        let expected = Truncated {
+            content: vec![
+                vec![ParagraphNode::Text(
+                    "I have a feeling this will appeal to multiple people for multiple reasons."
+                        .to_string(),
+                )],
+                vec![
+                    ParagraphNode::Text("[original source:".to_string()),
+                    ParagraphNode::Ellipsis,
+                ],
+            ],
+            is_truncated: true,
+        };
+        assert_eq!(truncate_content(&content, 100), expected);
+
+        // 109818097593839444 on mastodon.social
+        let content = parse_content(include_str!("tests/real_with_mentions.html"));
+        let expected = Truncated {
+            content: vec![vec![
+                ParagraphNode::Link(
+                    LinkKind::Mention(Mention {
+                        href: "https://mastodon.org.uk/@cybette".to_string(),
+                        host: "mastodon.org.uk".to_string(),
+                        user: "@cybette".to_string(),
+                    }),
+                    vec![LinkNode::Text("@cybette".to_string())],
+                ),
+                ParagraphNode::Text(" nice".to_string()),
+                ParagraphNode::Ellipsis,
+            ]],
+            is_truncated: true,
+        };
+        assert_eq!(truncate_content(&content, 14), expected);
+
+        let content = vec![vec![ParagraphNode::Link(
+            LinkKind::Link(Link::new("https://example.com".to_string())),
+            vec![LinkNode::Text("example.com".to_string())],
+        )]];
+        let expected = Truncated {
+            content: vec![vec![ParagraphNode::Ellipsis]],
+            is_truncated: true,
+        };
+        assert_eq!(truncate_content(&content, 5), expected);
+    }
+
+    #[test]
+    fn test_truncate_paragraph_boundary() {
+        let content = parse_content("<p>Hello world</p><p>Goodbye</p>");
+        let expected = Truncated {
+            content: vec![vec![
+                ParagraphNode::Text("Hello world".to_string()),
+                ParagraphNode::Ellipsis,
+            ]],
+            is_truncated: true,
+        };
+        assert_eq!(truncate_content(&content, 11), expected);
+    }
+}
@@ -9,6 +9,7 @@
 use super::{Hashtag, Link, LinkKind, Mention};
 use kuchiki::traits::TendrilSink;
 use kuchiki::{parse_html, ElementData, NodeData, NodeRef};
+use percent_encoding::percent_decode_str;
 use std::collections::HashSet;
 use std::mem;
 use url::Url;
@@ -77,6 +78,25 @@ pub trait Visit {
     /// end of a link.
     fn end_link(&mut self, _link: &LinkKind) {}
 
+    /// Hidden text
+    ///
+    /// Implement this method to be notified about text that
+    /// is not displayed. Mastodon hides the protocol and the
+    /// end of long links, so that they fit in the status.
+    ///
+    /// This usually happens inside a link element.
+    fn hidden_text(&mut self, _text: String) {}
+
+    /// Ellipsis
+    ///
+    /// Implement this method to be notified about an ellipsis,
+    /// that follows the visible part of a long link.
+    ///
+    /// By default, an ellipsis is notified as text.
+    fn ellipsis(&mut self) {
+        self.text("…".to_string())
+    }
+
     /// The end of the content has been reached
     ///
     /// Output must be produced at that step.
@@ -87,17 +107,31 @@ pub trait Visit {
 ///
 /// This function uses an implementation of [`Visit`]
 /// to extract information from a Mastodon content.
+///
+/// Relative links are not resolved, see [`visit_content_with_base`].
 pub fn visit_content<V>(content: &str, visitor: V) -> V::Output
 where
     V: Visit,
 {
     let node = parse_html().one(content);
-    Parser::new(visitor).parse(node)
+    Parser::new(visitor, None).parse(node)
+}
+
+/// Visit content, resolving links against a base URL
+///
+/// This function behaves like [`visit_content`], but relative
+/// links are resolved against `base`, usually the URL of the status.
+pub fn visit_content_with_base<V>(content: &str, base: &Url, visitor: V) -> V::Output
+where
+    V: Visit,
+{
+    let node = parse_html().one(content);
+    Parser::new(visitor, Some(base)).parse(node)
 }
 
 enum VisitKind {
-    Nothing,
     Children,
+    Hidden,
     Text(String),
     NewLine,
     Ellipsis,
@@ -105,18 +139,20 @@ enum VisitKind {
     Link(LinkKind),
 }
 
-struct Parser<V> {
+struct Parser<'a, V> {
     visitor: V,
+    base: Option<&'a Url>,
     current_text: String,
 }
 
-impl<V> Parser<V>
+impl<'a, V> Parser<'a, V>
 where
     V: Visit,
 {
-    fn new(visitor: V) -> Self {
+    fn new(visitor: V, base: Option<&'a Url>) -> Self {
         Parser {
             visitor,
+            base,
             current_text: String::new(),
         }
     }
@@ -130,8 +166,14 @@ where
     fn visit(&mut self, node: NodeRef) {
         let kind = self.find_visit_kind(&node);
         match kind {
-            VisitKind::Nothing => {}
             VisitKind::Children => self.visit_children(node),
+            VisitKind::Hidden => {
+                self.commit_string();
+                let text = node.text_contents();
+                if !text.is_empty() {
+                    self.visitor.hidden_text(text);
+                }
+            }
             VisitKind::Text(text) => self.current_text.push_str(&text),
             VisitKind::NewLine => {
                 self.commit_string();
@@ -139,7 +181,8 @@ where
             }
             VisitKind::Ellipsis => {
                 self.visit_children(node);
-                self.current_text.push('…');
+                self.commit_string();
+                self.visitor.ellipsis();
             }
             VisitKind::Paragraph => {
                 self.commit_string();
@@ -160,7 +203,7 @@ where
 
     fn find_visit_kind(&self, node: &NodeRef) -> VisitKind {
         match node.data() {
-            NodeData::Element(element) => Self::find_visit_kind_element(element),
+            NodeData::Element(element) => self.find_visit_kind_element(element),
             NodeData::Text(text) => {
                 let text = text.borrow();
                 VisitKind::Text(text.clone())
@@ -169,7 +212,7 @@ where
         }
     }
 
-    fn find_visit_kind_element(element: &ElementData) -> VisitKind {
+    fn find_visit_kind_element(&self, element: &ElementData) -> VisitKind {
         match &*element.name.local {
             "p" => VisitKind::Paragraph,
             "a" => {
@@ -177,13 +220,13 @@ where
                 let class = attributes.get("class").unwrap_or("");
                 let classes = class.split(' ').collect::<HashSet<_>>();
                 let href = attributes.get("href").unwrap_or("");
-                VisitKind::Link(Self::parse_href(href.to_string(), classes))
+                VisitKind::Link(self.parse_href(href.to_string(), classes))
             }
             "span" => {
                 let attributes = element.attributes.borrow();
                 let class = attributes.get("class").unwrap_or("");
                 match class {
-                    "invisible" => VisitKind::Nothing,
+                    "invisible" => VisitKind::Hidden,
                     "ellipsis" => VisitKind::Ellipsis,
                     _ => VisitKind::Children,
                 }
@@ -193,27 +236,27 @@ where
         }
     }
 
-    fn parse_href(href: String, classes: HashSet<&str>) -> LinkKind {
+    fn parse_href(&self, href: String, classes: HashSet<&str>) -> LinkKind {
         if let Some(element) = Self::parse_special_href(&href, classes) {
             element
         } else {
-            LinkKind::Link(Link { href })
+            LinkKind::Link(Link::with_base(href, self.base))
         }
     }
 
     fn parse_special_href(href: &str, classes: HashSet<&str>) -> Option<LinkKind> {
         let url = Url::parse(href).ok()?;
         if classes.contains("hashtag") {
-            let segments = url.path_segments()?;
-            let tag = segments.last()?;
+            let mut segments = url.path_segments()?;
+            let tag = segments.next_back()?;
             Some(LinkKind::Hashtag(Hashtag {
                 href: href.to_string(),
-                tag: tag.to_string(),
+                tag: percent_decode_str(tag).decode_utf8_lossy().into_owned(),
             }))
         } else if classes.contains("mention") {
             let host = url.host_str()?;
-            let segments = url.path_segments()?;
-            let user = segments.last()?;
+            let mut segments = url.path_segments()?;
+            let user = segments.next_back()?;
             Some(LinkKind::Mention(Mention {
                 href: href.to_string(),
                 host: host.to_string(),
@@ -250,6 +293,8 @@ mod tests {
         EndParagraph,
         BeginLink(LinkKind),
         EndLink(LinkKind),
+        HiddenText(String),
+        Ellipsis,
     }
 
     impl Visit for Vec<Node> {
@@ -279,6 +324,14 @@ mod tests {
             self.push(Node::EndLink(link.clone()))
         }
 
+        fn hidden_text(&mut self, text: String) {
+            self.push(Node::HiddenText(text))
+        }
+
+        fn ellipsis(&mut self) {
+            self.push(Node::Ellipsis)
+        }
+
         fn finalize(self) -> Self::Output {
             self
         }
@@ -297,15 +350,16 @@ mod tests {
             Node::EndParagraph,
             Node::BeginParagraph,
             Node::Text("[original source: ".to_string()),
-            Node::BeginLink(LinkKind::Link(Link {
-                href: "https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/"
-                    .to_string(),
-            })),
-            Node::Text("reddit.com/r/comics/comments/1…".to_string()),
-            Node::EndLink(LinkKind::Link(Link {
-                href: "https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/"
-                    .to_string(),
-            })),
+            Node::BeginLink(LinkKind::Link(Link::new(
+                "https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/".to_string(),
+            ))),
+            Node::HiddenText("https://www.".to_string()),
+            Node::Text("reddit.com/r/comics/comments/1".to_string()),
+            Node::Ellipsis,
+            Node::HiddenText("0rukp8/oc_magic_coding/".to_string()),
+            Node::EndLink(LinkKind::Link(Link::new(
+                "https://www.reddit.com/r/comics/comments/10rukp8/oc_magic_coding/".to_string(),
+            ))),
             Node::Text("]".to_string()),
             Node::EndParagraph,
             Node::BeginParagraph,
@@ -343,6 +397,23 @@ mod tests {
         assert_eq!(visit_content(content, Vec::new()), expected);
     }
 
+    #[test]
+    fn test_encoded_hashtag() {
+        let tag = |href: &str| {
+            let content = format!(
+                r#"<p><a href="{}" class="mention hashtag">#tag</a></p>"#,
+                href
+            );
+            match &visit_content(&content, Vec::new())[1] {
+                Node::BeginLink(LinkKind::Hashtag(hashtag)) => hashtag.tag.clone(),
+                node => panic!("unexpected node {:?}", node),
+            }
+        };
+        assert_eq!(tag("https://mastodon.social/tags/caf%C3%A9"), "café");
+        assert_eq!(tag("https://mastodon.social/tags/100%25"), "100%");
+        assert_eq!(tag("https://mastodon.social/tags/%FF"), "\u{FFFD}");
+    }
+
     #[test]
     fn test_real_with_mentions() {
         // 109818097593839444 on mastodon.social
@@ -0,0 +1,535 @@
+//! Mastodon entities
+//!
+//! This module contains models for the entities returned by Mastodon's REST API,
+//! that can be deserialized with [`serde`]. Only the most common attributes are
+//! modelled, and unknown attributes are ignored.
+//!
+//! Content written in HTML, like [`Status::content`] or [`Account::note`], is
+//! exposed as [`Html`], that holds the raw HTML, and parses it with
+//! [`parse_content`] the first time the parsed representation is needed.
+//!
+//! Identifiers are strings, and dates are ISO 8601 strings, like in the API.
+
+use crate::content::parse::{parse_content, parse_content_with_base, ParagraphNode};
+use once_cell::sync::OnceCell;
+use serde::de::Deserializer;
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use url::Url;
+
+/// HTML content
+///
+/// Holds HTML content as returned by Mastodon, and its parsed
+/// representation, that is computed lazily and cached.
+#[derive(Clone, Default)]
+pub struct Html {
+    raw: String,
+    base: Option<Url>,
+    parsed: OnceCell<Vec<Vec<ParagraphNode>>>,
+}
+
+impl Html {
+    /// Create HTML content
+    pub fn new(raw: String) -> Self {
+        Html {
+            raw,
+            base: None,
+            parsed: OnceCell::new(),
+        }
+    }
+
+    /// Create HTML content, with relative links resolved against a base URL
+    pub fn with_base(raw: String, base: Url) -> Self {
+        Html {
+            raw,
+            base: Some(base),
+            parsed: OnceCell::new(),
+        }
+    }
+
+    /// Raw HTML
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Parsed content
+    ///
+    /// The content is parsed with [`parse_content`] on the first call,
+    /// and cached for the next calls. Relative links are resolved against
+    /// the base URL, if any, like the URL of a status for its content.
+    pub fn parsed(&self) -> &[Vec<ParagraphNode>] {
+        self.parsed.get_or_init(|| match &self.base {
+            Some(base) => parse_content_with_base(&self.raw, base),
+            None => parse_content(&self.raw),
+        })
+    }
+}
+
+impl fmt::Debug for Html {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Html").field(&self.raw).finish()
+    }
+}
+
+impl PartialEq for Html {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl Eq for Html {}
+
+impl From<String> for Html {
+    fn from(raw: String) -> Self {
+        Html::new(raw)
+    }
+}
+
+impl Serialize for Html {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for Html {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(Html::new)
+    }
+}
+
+/// A list specifying visibilities of a status
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Visibility {
+    /// Visible to everyone, shown in public timelines
+    Public,
+    /// Visible to everyone, but not shown in public timelines
+    Unlisted,
+    /// Visible to followers only
+    Private,
+    /// Visible to mentioned users only
+    Direct,
+    /// A visibility that is not supported by Mastodon, like the `local`
+    /// visibility of some forks
+    #[serde(other)]
+    Unknown,
+}
+
+/// A list specifying types of media attachments
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum MediaType {
+    /// A static image
+    Image,
+    /// A looping, soundless animation
+    Gifv,
+    /// A video
+    Video,
+    /// An audio track
+    Audio,
+    /// An unsupported or unrecognized file type
+    #[serde(other)]
+    Unknown,
+}
+
+/// A custom emoji
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Emoji {
+    /// Shortcode, without the surrounding colons
+    pub shortcode: String,
+    /// URL of the emoji
+    pub url: String,
+    /// URL of a static version of the emoji
+    pub static_url: String,
+    /// Whether the emoji is displayed in the emoji picker
+    pub visible_in_picker: bool,
+    /// Category of the emoji
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+/// An account
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Account {
+    /// Identifier
+    pub id: String,
+    /// Username, without the domain
+    pub username: String,
+    /// Webfinger account URI
+    ///
+    /// Equal to `username` for local accounts, and to
+    /// `username@domain` for remote accounts.
+    pub acct: String,
+    /// Display name
+    pub display_name: String,
+    /// Whether follow requests are manually approved
+    pub locked: bool,
+    /// Whether the account is automated
+    #[serde(default)]
+    pub bot: bool,
+    /// Creation date
+    pub created_at: String,
+    /// Profile bio
+    pub note: Html,
+    /// URL of the profile page
+    pub url: String,
+    /// URL of the avatar
+    pub avatar: String,
+    /// URL of a static version of the avatar
+    pub avatar_static: String,
+    /// URL of the header
+    pub header: String,
+    /// URL of a static version of the header
+    pub header_static: String,
+    /// Number of followers
+    pub followers_count: u64,
+    /// Number of followed accounts
+    pub following_count: u64,
+    /// Number of statuses
+    pub statuses_count: u64,
+    /// Custom emojis used in the display name and the note
+    #[serde(default)]
+    pub emojis: Vec<Emoji>,
+}
+
+/// A media attachment
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct MediaAttachment {
+    /// Identifier
+    pub id: String,
+    /// Type of the media
+    #[serde(rename = "type")]
+    pub media_type: MediaType,
+    /// URL of the media
+    ///
+    /// `None` while the media is being processed.
+    pub url: Option<String>,
+    /// URL of a scaled-down preview
+    pub preview_url: Option<String>,
+    /// URL of the media on the remote server
+    #[serde(default)]
+    pub remote_url: Option<String>,
+    /// Alternative text
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Blurhash of the media
+    #[serde(default)]
+    pub blurhash: Option<String>,
+}
+
+/// A mentioned account
+///
+/// This entity is the mention as returned by the API. See
+/// [`content::Mention`](crate::content::Mention) for mentions
+/// found in content.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Mention {
+    /// Identifier of the account
+    pub id: String,
+    /// Username, without the domain
+    pub username: String,
+    /// URL of the profile page
+    pub url: String,
+    /// Webfinger account URI
+    pub acct: String,
+}
+
+/// A hashtag used in a status
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Tag {
+    /// Name of the hashtag, without the # prefix
+    pub name: String,
+    /// URL of the hashtag
+    pub url: String,
+}
+
+/// A status
+// The content is resolved against the URL of the status after deserialization
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(remote = "Self")]
+#[non_exhaustive]
+pub struct Status {
+    /// Identifier
+    pub id: String,
+    /// URI used for federation
+    pub uri: String,
+    /// URL of the HTML representation of the status
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Creation date
+    pub created_at: String,
+    /// Author of the status
+    pub account: Account,
+    /// Content
+    pub content: Html,
+    /// Visibility
+    pub visibility: Visibility,
+    /// Whether the media attachments are sensitive
+    pub sensitive: bool,
+    /// Content warning
+    ///
+    /// Empty when there is no content warning.
+    pub spoiler_text: String,
+    /// Media attachments
+    pub media_attachments: Vec<MediaAttachment>,
+    /// Mentioned accounts
+    pub mentions: Vec<Mention>,
+    /// Hashtags
+    pub tags: Vec<Tag>,
+    /// Custom emojis used in the content
+    pub emojis: Vec<Emoji>,
+    /// Number of boosts
+    pub reblogs_count: u64,
+    /// Number of favourites
+    pub favourites_count: u64,
+    /// Number of replies
+    pub replies_count: u64,
+    /// Identifier of the status this status replies to
+    #[serde(default)]
+    pub in_reply_to_id: Option<String>,
+    /// Identifier of the account this status replies to
+    #[serde(default)]
+    pub in_reply_to_account_id: Option<String>,
+    /// Boosted status
+    #[serde(default)]
+    pub reblog: Option<Box<Status>>,
+    /// Language of the status, as an ISO 639 code
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Edition date
+    #[serde(default)]
+    pub edited_at: Option<String>,
+    /// Whether the authenticated user favourited this status
+    #[serde(default)]
+    pub favourited: Option<bool>,
+    /// Whether the authenticated user boosted this status
+    #[serde(default)]
+    pub reblogged: Option<bool>,
+    /// Whether the authenticated user bookmarked this status
+    #[serde(default)]
+    pub bookmarked: Option<bool>,
+}
+
+impl<'de> Deserialize<'de> for Status {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut status = Status::deserialize(deserializer)?;
+        let base = status.url.as_deref().unwrap_or(&status.uri);
+        if let Ok(base) = Url::parse(base) {
+            let raw = std::mem::take(&mut status.content.raw);
+            status.content = Html::with_base(raw, base);
+        }
+        Ok(status)
+    }
+}
+
+impl Serialize for Status {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Status::serialize(self, serializer)
+    }
+}
+
+/// Ancestors and descendants of a status
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Context {
+    /// Statuses the status replies to, from the root of the thread
+    pub ancestors: Vec<Status>,
+    /// Replies to the status
+    pub descendants: Vec<Status>,
+}
+
+/// A status scheduled to be posted later
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct ScheduledStatus {
+    /// Identifier
+    pub id: String,
+    /// Date the status will be posted
+    pub scheduled_at: String,
+    /// Media attachments
+    #[serde(default)]
+    pub media_attachments: Vec<MediaAttachment>,
+}
+
+/// A list specifying types of notifications
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum NotificationType {
+    /// Someone mentioned the user
+    Mention,
+    /// Someone the user enabled notifications for posted a status
+    Status,
+    /// Someone boosted a status of the user
+    Reblog,
+    /// Someone followed the user
+    Follow,
+    /// Someone requested to follow the user
+    FollowRequest,
+    /// Someone favourited a status of the user
+    Favourite,
+    /// A poll has ended
+    Poll,
+    /// A status the user interacted with was edited
+    Update,
+    /// An unsupported or unrecognized notification type
+    #[serde(other)]
+    Unknown,
+}
+
+/// A notification
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Notification {
+    /// Identifier
+    pub id: String,
+    /// Type of the notification
+    #[serde(rename = "type")]
+    pub notification_type: NotificationType,
+    /// Creation date
+    pub created_at: String,
+    /// Account that caused the notification
+    pub account: Account,
+    /// Status attached to the notification
+    #[serde(default)]
+    pub status: Option<Status>,
+}
+
+/// A conversation with direct messages
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Conversation {
+    /// Identifier
+    pub id: String,
+    /// Whether the conversation has unread statuses
+    pub unread: bool,
+    /// Participants of the conversation
+    pub accounts: Vec<Account>,
+    /// Last status of the conversation
+    #[serde(default)]
+    pub last_status: Option<Status>,
+}
+
+/// An announcement set by administrators
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[non_exhaustive]
+pub struct Announcement {
+    /// Identifier
+    pub id: String,
+    /// Content
+    pub content: Html,
+    /// Publication date
+    pub published_at: String,
+    /// Whether the announcement is relevant for whole days
+    #[serde(default)]
+    pub all_day: bool,
+    /// Whether the authenticated user read the announcement
+    #[serde(default)]
+    pub read: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::parse::LinkNode;
+    use crate::content::LinkKind;
+
+    #[test]
+    fn test_status() {
+        // 109818097593839444 on mastodon.social
+        let status: Status = serde_json::from_str(include_str!("entities/tests/status.json"))
+            .expect("Failed to deserialize status");
+        assert_eq!(status.id, "109818097593839444");
+        assert_eq!(status.visibility, Visibility::Public);
+        assert_eq!(status.account.acct, "SfietKonstantin");
+        assert_eq!(status.mentions.len(), 2);
+        assert_eq!(status.mentions[0].acct, "cybette@mastodon.org.uk");
+        assert_eq!(status.media_attachments[0].media_type, MediaType::Image);
+        assert_eq!(status.emojis[0].shortcode, "blobcat");
+        assert_eq!(status.reblog, None);
+        assert_eq!(
+            status.content.parsed(),
+            &parse_content(include_str!("content/tests/real_with_mentions.html"))[..]
+        );
+    }
+
+    #[test]
+    fn test_account_note() {
+        let status: Status = serde_json::from_str(include_str!("entities/tests/status.json"))
+            .expect("Failed to deserialize status");
+        let note = status.account.note.parsed();
+        match &note[0][1] {
+            ParagraphNode::Link(LinkKind::Link(link), children) => {
+                assert_eq!(link.href, "https://github.com/SfietKonstantin/crabodon");
+                assert_eq!(
+                    children[1],
+                    LinkNode::Text("github.com/SfietKonstantin/crabodon".to_string())
+                );
+            }
+            node => panic!("unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let status: Status = serde_json::from_str(include_str!("entities/tests/status.json"))
+            .expect("Failed to deserialize status");
+        let json = serde_json::to_string(&status).expect("Failed to serialize status");
+        let deserialized: Status = serde_json::from_str(&json).expect("Failed to deserialize");
+        assert_eq!(deserialized, status);
+        assert_eq!(deserialized.content.raw(), status.content.raw());
+    }
+
+    #[test]
+    fn test_unknown_visibility() {
+        let json = include_str!("entities/tests/status.json")
+            .replace(r#""visibility": "public""#, r#""visibility": "local""#);
+        let statuses: Vec<Status> =
+            serde_json::from_str(&format!("[{}]", json)).expect("Failed to deserialize");
+        assert_eq!(statuses[0].visibility, Visibility::Unknown);
+    }
+
+    #[test]
+    fn test_relative_link() {
+        let json = include_str!("entities/tests/status.json").replace(
+            r#""content": "<p>"#,
+            r#""content": "<p><a href=\"/about\">about</a></p><p>"#,
+        );
+        let status: Status = serde_json::from_str(&json).expect("Failed to deserialize status");
+        match &status.content.parsed()[0][0] {
+            ParagraphNode::Link(LinkKind::Link(link), _) => assert_eq!(
+                link.url.as_ref().map(Url::as_str),
+                Some("https://mastodon.social/about")
+            ),
+            node => panic!("unexpected node {:?}", node),
+        }
+    }
+
+    #[test]
+    fn test_unknown_media_type() {
+        let json = r#"{"id": "1", "type": "hologram", "url": null, "preview_url": null}"#;
+        let media: MediaAttachment = serde_json::from_str(json).expect("Failed to deserialize");
+        assert_eq!(media.media_type, MediaType::Unknown);
+        assert_eq!(media.description, None);
+    }
+}
@@ -0,0 +1,380 @@
+//! Errors
+
+use crate::client::timestamp::parse_timestamp;
+use crate::client::transport::Response;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::SystemTime;
+
+/// A `crabodon` error
+///
+/// Errors returned by Mastodon are sorted by their meaning, so that applications
+/// can react to them: ask the user to log in again when the token was revoked,
+/// highlight invalid fields, or wait for the rate limit to reset.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The request could not be sent
+    Transport(Box<dyn StdError + Send + Sync>),
+    /// The access token is missing, invalid or revoked
+    ///
+    /// Mastodon responded with `401 Unauthorized`.
+    Unauthorized {
+        /// Error message returned by Mastodon, if any
+        message: Option<String>,
+    },
+    /// The action is not allowed
+    ///
+    /// Mastodon responded with `403 Forbidden`, for instance when the token
+    /// lacks a scope, or the account is suspended.
+    Forbidden {
+        /// Error message returned by Mastodon, if any
+        message: Option<String>,
+    },
+    /// The resource does not exist, or is not visible
+    ///
+    /// Mastodon responded with `404 Not Found`.
+    NotFound {
+        /// Error message returned by Mastodon, if any
+        message: Option<String>,
+    },
+    /// The resource was deleted
+    ///
+    /// Mastodon responded with `410 Gone`.
+    Gone {
+        /// Error message returned by Mastodon, if any
+        message: Option<String>,
+    },
+    /// The request was invalid
+    ///
+    /// Mastodon responded with `422 Unprocessable Entity`.
+    Validation {
+        /// Error message returned by Mastodon, if any
+        message: Option<String>,
+        /// Errors of each field, if any
+        details: Vec<FieldError>,
+    },
+    /// The rate limit was reached
+    ///
+    /// Mastodon responded with `429 Too Many Requests`.
+    RateLimited {
+        /// When the limit resets, if known
+        reset: Option<SystemTime>,
+    },
+    /// The server responded with another error status code
+    Http {
+        /// Status code
+        status: u16,
+        /// Error message returned by Mastodon, if any
+        message: Option<String>,
+    },
+    /// The server did not complete an operation in time
+    ///
+    /// For instance, the processing of an uploaded media did not complete.
+    Timeout,
+    /// The authorization was denied or failed
+    Authorization {
+        /// OAuth error code, like `access_denied`
+        error: String,
+        /// Description of the error, if any
+        description: Option<String>,
+    },
+    /// The response could not be decoded
+    Decode(serde_json::Error),
+    /// The URL of the request is invalid
+    Url(url::ParseError),
+    /// An I/O error occurred
+    Io(std::io::Error),
+    /// An image could not be processed
+    #[cfg(feature = "image")]
+    Image(image::ImageError),
+}
+
+/// An error of a field
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct FieldError {
+    /// Name of the field, like `text`
+    pub field: String,
+    /// Error code, like `ERR_BLANK`
+    pub error: String,
+    /// Description of the error, like `can't be blank`
+    pub description: String,
+}
+
+impl Error {
+    /// Status code of the response, if the server responded with an error
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Unauthorized { .. } => Some(401),
+            Error::Forbidden { .. } => Some(403),
+            Error::NotFound { .. } => Some(404),
+            Error::Gone { .. } => Some(410),
+            Error::Validation { .. } => Some(422),
+            Error::RateLimited { .. } => Some(429),
+            Error::Http { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Error message returned by Mastodon, if any
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            Error::Unauthorized { message }
+            | Error::Forbidden { message }
+            | Error::NotFound { message }
+            | Error::Gone { message }
+            | Error::Validation { message, .. }
+            | Error::Http { message, .. } => message.as_deref(),
+            Error::Authorization { description, .. } => description.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Create an error from an error response
+    pub(crate) fn from_response(response: &Response) -> Self {
+        let body = serde_json::from_slice::<ErrorBody>(&response.body).ok();
+        if let Some(ErrorBody {
+            error,
+            error_description: Some(description),
+            ..
+        }) = body
+        {
+            // OAuth errors come with a description
+            if response.status == 400 || response.status == 401 {
+                return Error::Authorization {
+                    error,
+                    description: Some(description),
+                };
+            }
+            let message = Some(format!("{}: {}", error, description));
+            return Error::from_status(response, message, Vec::new());
+        }
+        let (message, details) = match body {
+            Some(body) => (Some(body.error), body.details.into_field_errors()),
+            None => (None, Vec::new()),
+        };
+        Error::from_status(response, message, details)
+    }
+
+    fn from_status(response: &Response, message: Option<String>, details: Vec<FieldError>) -> Self {
+        match response.status {
+            422 => Error::Validation { message, details },
+            429 => Error::RateLimited {
+                reset: response
+                    .header("X-RateLimit-Reset")
+                    .and_then(parse_timestamp),
+            },
+            status => Error::with_status(status, message),
+        }
+    }
+
+    /// Create an error from a status code and a message
+    ///
+    /// This is used for errors reported without a response, like errors of
+    /// the streaming API. Streaming connectors also use it to report the status
+    /// of a connection that failed, see [`SseConnector`](crate::streaming::SseConnector).
+    pub fn with_status(status: u16, message: Option<String>) -> Self {
+        match status {
+            401 => Error::Unauthorized { message },
+            403 => Error::Forbidden { message },
+            404 => Error::NotFound { message },
+            410 => Error::Gone { message },
+            422 => Error::Validation {
+                message,
+                details: Vec::new(),
+            },
+            429 => Error::RateLimited { reset: None },
+            status => Error::Http { status, message },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(error) => write!(f, "Transport error: {}", error),
+            Error::Authorization {
+                error,
+                description: Some(description),
+            } => write!(f, "Authorization failed: {} ({})", error, description),
+            Error::Authorization {
+                error,
+                description: None,
+            } => write!(f, "Authorization failed: {}", error),
+            Error::RateLimited { .. } => write!(f, "Rate limit reached"),
+            Error::Timeout => write!(f, "Operation timed out"),
+            Error::Decode(error) => write!(f, "Invalid response: {}", error),
+            Error::Url(error) => write!(f, "Invalid URL: {}", error),
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            #[cfg(feature = "image")]
+            Error::Image(error) => write!(f, "Image error: {}", error),
+            _ => {
+                let status = self.status().unwrap_or(0);
+                match self.message() {
+                    Some(message) => write!(f, "Server error {}: {}", status, message),
+                    None => write!(f, "Server error {}", status),
+                }
+            }
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Transport(error) => Some(error.as_ref()),
+            Error::Decode(error) => Some(error),
+            Error::Url(error) => Some(error),
+            Error::Io(error) => Some(error),
+            #[cfg(feature = "image")]
+            Error::Image(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Decode(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<getrandom::Error> for Error {
+    // `io::Error::other` is not available with the MSRV
+    #[allow(clippy::io_other_error)]
+    fn from(error: getrandom::Error) -> Self {
+        let error = match error.raw_os_error() {
+            Some(code) => std::io::Error::from_raw_os_error(code),
+            None => std::io::Error::new(std::io::ErrorKind::Other, error.to_string()),
+        };
+        Error::Io(error)
+    }
+}
+
+#[cfg(feature = "image")]
+impl From<image::ImageError> for Error {
+    fn from(error: image::ImageError) -> Self {
+        Error::Image(error)
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(error: url::ParseError) -> Self {
+        Error::Url(error)
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+    #[serde(default)]
+    details: Details,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(transparent)]
+struct Details(BTreeMap<String, Vec<DetailBody>>);
+
+#[derive(Deserialize)]
+struct DetailBody {
+    error: String,
+    description: String,
+}
+
+impl Details {
+    fn into_field_errors(self) -> Vec<FieldError> {
+        self.0
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.into_iter().map(move |detail| FieldError {
+                    field: field.clone(),
+                    error: detail.error,
+                    description: detail.description,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn error(status: u16, body: &str) -> Error {
+        Error::from_response(&Response::new(status, Vec::new(), body.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn test_status_errors() {
+        assert!(matches!(
+            error(401, r#"{"error": "The access token was revoked"}"#),
+            Error::Unauthorized { message: Some(_) }
+        ));
+        assert!(matches!(
+            error(404, r#"{"error": "Record not found"}"#),
+            Error::NotFound { message: Some(_) }
+        ));
+        assert!(matches!(error(410, ""), Error::Gone { message: None }));
+        let error = error(502, "<html>Bad gateway</html>");
+        assert_eq!(error.status(), Some(502));
+        assert_eq!(error.to_string(), "Server error 502");
+    }
+
+    #[test]
+    fn test_validation_error() {
+        let body = r#"{
+            "error": "Validation failed: Text can't be blank",
+            "details": {
+                "text": [{"error": "ERR_BLANK", "description": "can't be blank"}]
+            }
+        }"#;
+        match error(422, body) {
+            Error::Validation { message, details } => {
+                assert_eq!(
+                    message.as_deref(),
+                    Some("Validation failed: Text can't be blank")
+                );
+                assert_eq!(
+                    details,
+                    vec![FieldError {
+                        field: "text".to_string(),
+                        error: "ERR_BLANK".to_string(),
+                        description: "can't be blank".to_string(),
+                    }]
+                );
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_authorization_and_rate_limit_errors() {
+        let error = error(
+            400,
+            r#"{"error": "invalid_grant", "error_description": "The provided authorization grant is invalid."}"#,
+        );
+        assert!(matches!(&error, Error::Authorization { error, .. } if error == "invalid_grant"));
+
+        let headers = vec![(
+            "X-RateLimit-Reset".to_string(),
+            "1970-01-01T00:01:00Z".to_string(),
+        )];
+        let response = Response::new(429, headers, Vec::new());
+        assert!(matches!(
+            Error::from_response(&response),
+            Error::RateLimited { reset: Some(reset) } if reset == UNIX_EPOCH + Duration::from_secs(60)
+        ));
+    }
+}
@@ -6,8 +6,55 @@
 //!
 //! Mastodon status content content is written in HTML, and HTML is notoriously hard to parse
 //! correctly. `crabodon` ships the [`content`] module to help dealing with them.
+//!
+//! # Composing statuses
+//!
+//! Statuses are composed as plain text, that Mastodon converts to HTML. `crabodon` ships the
+//! [`compose`] module to help analyzing the text being composed.
+//!
+//! # Entities
+//!
+//! Mastodon's REST API returns entities like statuses or accounts as JSON. `crabodon` ships
+//! the [`entities`] module, with models for those entities that can be deserialized with `serde`.
+//!
+//! # REST API client
+//!
+//! `crabodon` ships the [`client`] module, that calls Mastodon's REST API over a pluggable
+//! HTTP transport.
+//!
+//! # Streaming
+//!
+//! Live events, like new statuses or notifications, are pushed by Mastodon's streaming API.
+//! `crabodon` ships the [`streaming`] module, that receives them over server-sent events or a
+//! WebSocket.
+//!
+//! # Authorization
+//!
+//! Applications are authorized with OAuth 2. `crabodon` ships the [`auth`] module, that
+//! registers applications and obtains access tokens.
+//!
+//! # Errors
+//!
+//! Fallible functions return an [`Error`], that tells apart transport failures, revoked
+//! tokens, validation errors, rate limiting and other errors returned by Mastodon.
+//!
+//! # Features
+//!
+//! The `image` feature prepares images locally before they are uploaded: it applies their
+//! orientation, strips their metadata, and downscales them to the limits of the instance.
+//! This feature is not covered by the minimum supported Rust version (1.52.1), as the
+//! `image` crate requires a more recent compiler.
 
 #![warn(missing_docs)]
 #![forbid(unsafe_code)]
 
+pub mod auth;
+mod backoff;
+pub mod client;
+pub mod compose;
 pub mod content;
+pub mod entities;
+mod error;
+pub mod streaming;
+
+pub use error::{Error, FieldError};
@@ -0,0 +1,261 @@
+//! Stream live events
+//!
+//! Mastodon pushes live events, like new statuses in a timeline, or notifications,
+//! with its streaming API. Events can be received as server-sent events, with one
+//! HTTP connection per [`Stream`], or with a single WebSocket connection, that can
+//! subscribe to several streams.
+//!
+//! This module decodes events into [`Event`]s. [`sse`] parses server-sent events, and
+//! [`websocket`] parses WebSocket messages. Both modules offer an iterator over the
+//! events, that reconnects when the connection is lost.
+//!
+//! Like [`Client`](crate::client::Client), this module does not open connections
+//! itself. Connections are opened by implementations of [`SseConnector`] or
+//! [`WebSocketConnector`].
+
+pub mod sse;
+#[cfg(test)]
+pub(crate) mod stub;
+pub mod websocket;
+
+use crate::backoff::backoff;
+use crate::entities::{Announcement, Conversation, Notification, Status};
+use crate::Error;
+pub use sse::{SseConnector, SseStream};
+use std::error::Error as StdError;
+use std::time::Duration;
+pub use websocket::{WebSocket, WebSocketConnector, WebSocketStream};
+
+/// A list specifying streams
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Stream {
+    /// Events of the authenticated user, including the home timeline
+    User,
+    /// Notifications of the authenticated user
+    UserNotification,
+    /// Public timeline
+    Public,
+    /// Local public timeline
+    PublicLocal,
+    /// Timeline of a hashtag, without the # prefix
+    Hashtag(String),
+    /// Local timeline of a hashtag, without the # prefix
+    HashtagLocal(String),
+    /// Timeline of a list, by identifier
+    List(String),
+    /// Direct messages
+    Direct,
+}
+
+impl Stream {
+    /// Name of the stream
+    ///
+    /// This is the name used by Mastodon, like `public:local`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stream::User => "user",
+            Stream::UserNotification => "user:notification",
+            Stream::Public => "public",
+            Stream::PublicLocal => "public:local",
+            Stream::Hashtag(_) => "hashtag",
+            Stream::HashtagLocal(_) => "hashtag:local",
+            Stream::List(_) => "list",
+            Stream::Direct => "direct",
+        }
+    }
+
+    /// Parameter of the stream
+    ///
+    /// Hashtag streams take a `tag`, and list streams take a `list`.
+    pub fn param(&self) -> Option<(&'static str, &str)> {
+        match self {
+            Stream::Hashtag(tag) | Stream::HashtagLocal(tag) => Some(("tag", tag)),
+            Stream::List(list) => Some(("list", list)),
+            _ => None,
+        }
+    }
+}
+
+/// A streaming event
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A new status
+    Update(Box<Status>),
+    /// A status was deleted, by identifier
+    Delete(String),
+    /// A new notification
+    Notification(Box<Notification>),
+    /// A status was edited
+    StatusUpdate(Box<Status>),
+    /// Filters of the user changed
+    FiltersChanged,
+    /// A direct conversation was updated
+    Conversation(Box<Conversation>),
+    /// A new announcement
+    Announcement(Box<Announcement>),
+    /// An event that is not supported
+    Unknown {
+        /// Name of the event
+        event: String,
+        /// Payload of the event
+        payload: String,
+    },
+}
+
+impl Event {
+    /// Decode an event
+    ///
+    /// `event` is the name of the event, like `update`, and `payload` is its
+    /// data, usually JSON.
+    pub fn decode(event: &str, payload: &str) -> Result<Self, Error> {
+        let event = match event {
+            "update" => Event::Update(serde_json::from_str(payload)?),
+            "delete" => Event::Delete(payload.to_string()),
+            "notification" => Event::Notification(serde_json::from_str(payload)?),
+            "status.update" => Event::StatusUpdate(serde_json::from_str(payload)?),
+            "filters_changed" => Event::FiltersChanged,
+            "conversation" => Event::Conversation(serde_json::from_str(payload)?),
+            "announcement" => Event::Announcement(serde_json::from_str(payload)?),
+            event => Event::Unknown {
+                event: event.to_string(),
+                payload: payload.to_string(),
+            },
+        };
+        Ok(event)
+    }
+
+    /// Whether an event comes with a payload
+    pub(crate) fn has_payload(event: &str) -> bool {
+        matches!(
+            event,
+            "update"
+                | "delete"
+                | "notification"
+                | "status.update"
+                | "conversation"
+                | "announcement"
+        )
+    }
+}
+
+/// Reconnection policy
+///
+/// When the connection is lost, streams wait for a delay, and reconnect. The delay
+/// doubles after each failed attempt, up to a maximum. Regardless of the policy,
+/// streams stop when a connection is refused with a status that a new attempt
+/// cannot fix, like `401 Unauthorized` or `404 Not Found`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Reconnect {
+    /// Delay before the first attempt
+    pub initial_delay: Duration,
+    /// Maximum delay between attempts
+    pub max_delay: Duration,
+    /// Maximum number of consecutive failed attempts
+    ///
+    /// Streams stop after this number of failed attempts.
+    /// There is no limit if `None`.
+    pub max_attempts: Option<u32>,
+}
+
+impl Reconnect {
+    /// Create a reconnection policy
+    pub fn new(initial_delay: Duration, max_delay: Duration, max_attempts: Option<u32>) -> Self {
+        Reconnect {
+            initial_delay,
+            max_delay,
+            max_attempts,
+        }
+    }
+
+    /// Delay before an attempt
+    ///
+    /// `attempt` is the number of consecutive failed attempts.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        backoff(self.initial_delay, self.max_delay, attempt)
+    }
+
+    pub(crate) fn can_retry(&self, attempt: u32) -> bool {
+        self.max_attempts.map(|max| attempt < max).unwrap_or(true)
+    }
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Reconnect::new(Duration::from_secs(1), Duration::from_secs(60), None)
+    }
+}
+
+/// Convert an error of a connector
+///
+/// Connectors report the status of a refused connection with an [`Error`].
+pub(crate) fn connect_error(error: Box<dyn StdError + Send + Sync>) -> Error {
+    match error.downcast::<Error>() {
+        Ok(error) => *error,
+        Err(error) => Error::Transport(error),
+    }
+}
+
+/// Whether an error stops a stream
+///
+/// Streams stop when the token is invalid, when the access is forbidden, or
+/// when the stream does not exist, as reconnecting would fail the same way.
+pub(crate) fn is_final(error: &Error) -> bool {
+    matches!(
+        error.status(),
+        Some(401) | Some(403) | Some(404) | Some(410)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(crate) const STATUS: &str = include_str!("entities/tests/status.json");
+
+    #[test]
+    fn test_decode() {
+        match Event::decode("update", STATUS).unwrap() {
+            Event::Update(status) => assert_eq!(status.id, "109818097593839444"),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(
+            Event::decode("delete", "109818097593839444").unwrap(),
+            Event::Delete("109818097593839444".to_string())
+        );
+        assert_eq!(
+            Event::decode("filters_changed", "").unwrap(),
+            Event::FiltersChanged
+        );
+        assert_eq!(
+            Event::decode("encrypted_message", "{}").unwrap(),
+            Event::Unknown {
+                event: "encrypted_message".to_string(),
+                payload: "{}".to_string()
+            }
+        );
+        assert!(matches!(
+            Event::decode("update", "{}"),
+            Err(Error::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let reconnect = Reconnect::new(Duration::from_secs(1), Duration::from_secs(5), Some(3));
+        let delays = (0..4).map(|i| reconnect.delay(i)).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(4),
+                Duration::from_secs(5)
+            ]
+        );
+        assert!(reconnect.can_retry(2));
+        assert!(!reconnect.can_retry(3));
+    }
+}
@@ -0,0 +1,329 @@
+//! Server-sent events
+//!
+//! With server-sent events, Mastodon streams events over a long-lived HTTP
+//! response, one connection per stream. Each event is made of an `event:` line
+//! with the name of the event, and of `data:` lines with its payload, and ends
+//! with an empty line.
+//!
+//! [`SseParser`] parses those lines, and [`SseStream`] iterates over the events of
+//! a stream, reconnecting when the connection is lost.
+
+use super::{connect_error, is_final, Event, Reconnect, Stream};
+use crate::client::transport::{Method, Request};
+use crate::Error;
+use std::error::Error as StdError;
+use std::io::BufRead;
+use std::mem;
+use std::thread;
+use url::Url;
+
+/// A trait to open server-sent events connections
+///
+/// Implement this trait to use an HTTP library with [`SseStream`]. The connector
+/// should send the request, and return the body of the response, if the
+/// response status is a success. Otherwise, it should return an error created
+/// with [`Error::with_status`], so that the stream stops when reconnecting cannot
+/// succeed.
+pub trait SseConnector {
+    /// Body of the response
+    type Reader: BufRead;
+
+    /// Open a connection
+    fn connect(&self, request: Request) -> Result<Self::Reader, Box<dyn StdError + Send + Sync>>;
+}
+
+/// A server-sent events parser
+///
+/// Lines are fed one by one, and complete events are returned.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    event: String,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Create a parser
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a line
+    ///
+    /// Returns the name and the payload of an event when `line`
+    /// completes it.
+    pub fn feed(&mut self, line: &str) -> Option<(String, String)> {
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        if line.is_empty() {
+            if self.data.is_empty() {
+                self.event.clear();
+                return None;
+            }
+            let event = match mem::take(&mut self.event) {
+                event if event.is_empty() => "message".to_string(),
+                event => event,
+            };
+            let data = mem::take(&mut self.data).join("\n");
+            return Some((event, data));
+        }
+        if line.starts_with(':') {
+            // Comments are used as heartbeats
+            return None;
+        }
+
+        let mut parts = line.splitn(2, ':');
+        let field = parts.next().unwrap_or("");
+        let value = parts.next().unwrap_or("");
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+        }
+        None
+    }
+}
+
+/// An iterator over the events of a stream
+///
+/// Events are received as server-sent events. When the connection is lost, the
+/// stream reconnects according to its [`Reconnect`] policy, and the error is
+/// returned. The iterator ends when the maximum number of attempts is reached, or
+/// when the connection is refused with a status like `401 Unauthorized`.
+pub struct SseStream<C>
+where
+    C: SseConnector,
+{
+    connector: C,
+    request: Request,
+    reader: Option<C::Reader>,
+    parser: SseParser,
+    reconnect: Reconnect,
+    attempts: u32,
+    done: bool,
+}
+
+impl<C> SseStream<C>
+where
+    C: SseConnector,
+{
+    /// Create a stream
+    ///
+    /// `base` is the URL of the streaming API of the instance, usually the URL of
+    /// the instance. `token` is the access token, that is required by most streams.
+    /// The connection is opened on the first call to `next`.
+    pub fn new(
+        connector: C,
+        base: &Url,
+        stream: &Stream,
+        token: Option<&str>,
+    ) -> Result<Self, Error> {
+        let path = format!("api/v1/streaming/{}", stream.name().replace(':', "/"));
+        let mut url = base.join(&path)?;
+        if let Some((key, value)) = stream.param() {
+            url.query_pairs_mut().append_pair(key, value);
+        }
+        let mut request = Request::new(Method::Get, url);
+        request
+            .headers
+            .push(("Accept".to_string(), "text/event-stream".to_string()));
+        if let Some(token) = token {
+            request
+                .headers
+                .push(("Authorization".to_string(), format!("Bearer {}", token)));
+        }
+        Ok(SseStream {
+            connector,
+            request,
+            reader: None,
+            parser: SseParser::new(),
+            reconnect: Reconnect::default(),
+            attempts: 0,
+            done: false,
+        })
+    }
+
+    /// Set the reconnection policy
+    pub fn with_reconnect(mut self, reconnect: Reconnect) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    fn disconnect(&mut self) {
+        self.reader = None;
+        self.parser = SseParser::new();
+        self.attempts += 1;
+    }
+}
+
+impl<C> Iterator for SseStream<C>
+where
+    C: SseConnector,
+{
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let reader = match &mut self.reader {
+                Some(reader) => reader,
+                None => {
+                    if self.attempts > 0 {
+                        if !self.reconnect.can_retry(self.attempts - 1) {
+                            return None;
+                        }
+                        thread::sleep(self.reconnect.delay(self.attempts - 1));
+                    }
+                    let mut request = Request::new(self.request.method, self.request.url.clone());
+                    request.headers = self.request.headers.clone();
+                    match self.connector.connect(request) {
+                        Ok(reader) => self.reader.get_or_insert(reader),
+                        Err(error) => {
+                            let error = connect_error(error);
+                            self.attempts += 1;
+                            self.done = is_final(&error);
+                            return Some(Err(error));
+                        }
+                    }
+                }
+            };
+
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => self.disconnect(),
+                Ok(_) => {
+                    if let Some((event, data)) = self.parser.feed(&line) {
+                        self.attempts = 0;
+                        return Some(Event::decode(&event, &data));
+                    }
+                }
+                Err(error) => {
+                    self.disconnect();
+                    return Some(Err(Error::Io(error)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stub::{write_chunked, write_error, Handler, HttpConnector, StubServer};
+    use super::super::tests::STATUS;
+    use super::*;
+    use std::io::BufReader;
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    fn no_delay(max_attempts: u32) -> Reconnect {
+        Reconnect::new(
+            Duration::from_secs(0),
+            Duration::from_secs(0),
+            Some(max_attempts),
+        )
+    }
+
+    fn events(chunks: &'static [&'static str]) -> Handler {
+        Box::new(move |_: &str, reader: &mut BufReader<TcpStream>| {
+            write_chunked(reader.get_mut(), chunks)
+        })
+    }
+
+    #[test]
+    fn test_parser() {
+        let mut parser = SseParser::new();
+        assert_eq!(parser.feed(":thump\n"), None);
+        assert_eq!(parser.feed("event: delete\n"), None);
+        assert_eq!(parser.feed("data: 1234\r\n"), None);
+        assert_eq!(
+            parser.feed("\n"),
+            Some(("delete".to_string(), "1234".to_string()))
+        );
+        assert_eq!(parser.feed("data:a\n"), None);
+        assert_eq!(parser.feed("data: b\n"), None);
+        assert_eq!(
+            parser.feed("\n"),
+            Some(("message".to_string(), "a\nb".to_string()))
+        );
+        assert_eq!(parser.feed("\n"), None);
+    }
+
+    #[test]
+    fn test_stream() {
+        let update = format!("event: update\ndata: {}\n\n", STATUS.replace('\n', ""));
+        let server = StubServer::start(vec![Box::new(
+            move |_: &str, reader: &mut BufReader<TcpStream>| {
+                // Lines are split across chunks
+                let (start, end) = update.split_at(20);
+                let chunks = [":)\n\n", start, end, "event: del", "ete\ndata: 1\n", "\n"];
+                write_chunked(reader.get_mut(), &chunks);
+            },
+        )]);
+        let stream = SseStream::new(
+            HttpConnector,
+            &server.url(),
+            &Stream::HashtagLocal("rust".to_string()),
+            Some("token"),
+        )
+        .unwrap()
+        .with_reconnect(no_delay(1));
+
+        let events = stream.collect::<Vec<_>>();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[0], Ok(Event::Update(_))));
+        assert_eq!(events[1].as_ref().unwrap(), &Event::Delete("1".to_string()));
+        assert!(matches!(&events[2], Err(Error::Transport(_))));
+
+        let heads = server.join();
+        assert!(heads[0].starts_with("GET /api/v1/streaming/hashtag/local?tag=rust HTTP/1.1\r\n"));
+        assert!(heads[0].contains("\r\nAccept: text/event-stream\r\n"));
+        assert!(heads[0].contains("\r\nAuthorization: Bearer token\r\n"));
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let server = StubServer::start(vec![
+            events(&["event: delete\ndata: 1\n\n"]),
+            Box::new(|_: &str, reader: &mut BufReader<TcpStream>| {
+                write_error(reader.get_mut(), "503 Service Unavailable")
+            }),
+            events(&["event: delete\n", "data: 2\n\n"]),
+        ]);
+        let stream = SseStream::new(HttpConnector, &server.url(), &Stream::User, None)
+            .unwrap()
+            .with_reconnect(no_delay(2));
+
+        let events = stream
+            .map(|event| event.map_err(|error| error.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[..3],
+            [
+                Ok(Event::Delete("1".to_string())),
+                Err("Server error 503: Service Unavailable".to_string()),
+                Ok(Event::Delete("2".to_string())),
+            ]
+        );
+        // The server is gone
+        assert!(events[3..].iter().all(Result::is_err));
+        assert_eq!(server.join().len(), 3);
+    }
+
+    #[test]
+    fn test_final_status() {
+        let server = StubServer::start(vec![Box::new(
+            |_: &str, reader: &mut BufReader<TcpStream>| {
+                write_error(reader.get_mut(), "401 Unauthorized")
+            },
+        )]);
+        // Reconnects forever by default
+        let stream = SseStream::new(HttpConnector, &server.url(), &Stream::User, None).unwrap();
+
+        let events = stream.collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], Err(Error::Unauthorized { .. })));
+        assert_eq!(server.join().len(), 1);
+    }
+}
@@ -0,0 +1,347 @@
+//! A stub streaming server for tests
+//!
+//! The server listens on the loopback interface, and serves each connection with
+//! a handler. Clients speak just enough HTTP/1.1 and WebSocket to be connected to
+//! it, with chunked bodies and masked frames.
+
+use super::sse::SseConnector;
+use super::websocket::{WebSocket, WebSocketConnector};
+use crate::client::transport::Request;
+use crate::Error;
+use std::error::Error as StdError;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use url::Url;
+
+/// Key and accept values of the handshake example of RFC 6455
+const WEBSOCKET_KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+const WEBSOCKET_ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection handler
+///
+/// The handler receives the head of the request, and the connection.
+pub(crate) type Handler = Box<dyn FnOnce(&str, &mut BufReader<TcpStream>) + Send>;
+
+/// A server, serving one connection per handler
+///
+/// When all handlers are used, the server stops listening, and new connections
+/// are refused.
+pub(crate) struct StubServer {
+    address: SocketAddr,
+    thread: JoinHandle<Vec<String>>,
+}
+
+impl StubServer {
+    pub(crate) fn start(handlers: Vec<Handler>) -> Self {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let thread = thread::spawn(move || {
+            let mut heads = Vec::new();
+            for handler in handlers {
+                let (stream, _) = listener.accept().unwrap();
+                stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+                let mut reader = BufReader::new(stream);
+                let head = read_head(&mut reader).unwrap();
+                handler(&head, &mut reader);
+                heads.push(head);
+            }
+            // Refuse new connections before returning
+            drop(listener);
+            heads
+        });
+        StubServer { address, thread }
+    }
+
+    /// Base URL of the server
+    pub(crate) fn url(&self) -> Url {
+        Url::parse(&format!("http://{}", self.address)).unwrap()
+    }
+
+    /// Wait for the server to stop, and return the heads of the requests
+    pub(crate) fn join(self) -> Vec<String> {
+        self.thread.join().unwrap()
+    }
+}
+
+fn read_head(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut head = String::new();
+    loop {
+        let len = reader.read_line(&mut head)?;
+        if len == 0 || head.ends_with("\r\n\r\n") {
+            return Ok(head);
+        }
+    }
+}
+
+/// Check the status of a response
+///
+/// Returns an error with the status code if it is not the expected one.
+fn check_status(head: &str, expected: u16) -> Result<(), Box<dyn StdError + Send + Sync>> {
+    let line = head.lines().next().unwrap_or("");
+    let mut parts = line.splitn(3, ' ');
+    let status = parts.nth(1).and_then(|status| status.parse().ok());
+    let reason = parts.next().map(str::to_string);
+    match status {
+        Some(status) if status == expected => Ok(()),
+        Some(status) => Err(Error::with_status(status, reason).into()),
+        None => Err(format!("Invalid status line {}", line).into()),
+    }
+}
+
+/// Respond with a chunked body, sending each chunk separately
+pub(crate) fn write_chunked(stream: &mut TcpStream, chunks: &[&str]) {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n"
+    )
+    .unwrap();
+    for chunk in chunks {
+        write!(stream, "{:x}\r\n{}\r\n", chunk.len(), chunk).unwrap();
+        stream.flush().unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+    write!(stream, "0\r\n\r\n").unwrap();
+}
+
+/// Respond with an error
+pub(crate) fn write_error(stream: &mut TcpStream, status: &str) {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    )
+    .unwrap();
+}
+
+/// Accept a WebSocket handshake
+pub(crate) fn accept_websocket(head: &str, stream: &mut TcpStream) {
+    assert!(head.contains(&format!("Sec-WebSocket-Key: {}\r\n", WEBSOCKET_KEY)));
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        WEBSOCKET_ACCEPT
+    )
+    .unwrap();
+}
+
+/// Write a WebSocket frame
+///
+/// Frames sent by clients are masked.
+pub(crate) fn write_frame(
+    stream: &mut impl Write,
+    opcode: u8,
+    payload: &[u8],
+    masked: bool,
+) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    let mask_bit = if masked { 0x80 } else { 0 };
+    match payload.len() {
+        len if len < 126 => frame.push(mask_bit | len as u8),
+        len if len <= 0xffff => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    if masked {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+    } else {
+        frame.extend_from_slice(payload);
+    }
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+/// Read a WebSocket frame
+///
+/// Returns the opcode and the unmasked payload.
+pub(crate) fn read_frame(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as usize
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len) as usize
+        }
+        len => len as usize,
+    };
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    for (byte, mask) in payload.iter_mut().zip(mask.iter().cycle()) {
+        *byte ^= mask;
+    }
+    Ok((header[0] & 0x0f, payload))
+}
+
+/// Read a text frame sent by a client
+pub(crate) fn read_text(reader: &mut impl Read) -> String {
+    let (opcode, payload) = read_frame(reader).unwrap();
+    assert_eq!(opcode, 1);
+    String::from_utf8(payload).unwrap()
+}
+
+/// Send a text frame to a client
+pub(crate) fn write_text(stream: &mut TcpStream, text: &str) {
+    write_frame(stream, 1, text.as_bytes(), false).unwrap();
+}
+
+/// Close a WebSocket connection
+pub(crate) fn write_close(stream: &mut TcpStream) {
+    write_frame(stream, 8, &[], false).unwrap();
+}
+
+fn connect(url: &Url) -> io::Result<TcpStream> {
+    let address = format!(
+        "{}:{}",
+        url.host_str().unwrap_or(""),
+        url.port_or_known_default().unwrap_or(80)
+    );
+    let stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+fn target(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+/// A server-sent events client
+pub(crate) struct HttpConnector;
+
+impl SseConnector for HttpConnector {
+    type Reader = BufReader<ChunkedReader>;
+
+    fn connect(&self, request: Request) -> Result<Self::Reader, Box<dyn StdError + Send + Sync>> {
+        let mut stream = connect(&request.url)?;
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: {}\r\n",
+            target(&request.url),
+            request.url.host_str().unwrap_or("")
+        )?;
+        for (name, value) in &request.headers {
+            write!(stream, "{}: {}\r\n", name, value)?;
+        }
+        write!(stream, "\r\n")?;
+
+        let mut reader = BufReader::new(stream);
+        let head = read_head(&mut reader)?;
+        check_status(&head, 200)?;
+        if !head.contains("Transfer-Encoding: chunked\r\n") {
+            return Err("Body is not chunked".into());
+        }
+        Ok(BufReader::new(ChunkedReader {
+            reader,
+            remaining: 0,
+            done: false,
+        }))
+    }
+}
+
+/// A reader decoding a chunked body
+pub(crate) struct ChunkedReader {
+    reader: BufReader<TcpStream>,
+    remaining: usize,
+    done: bool,
+}
+
+impl Read for ChunkedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            let mut line = String::new();
+            // Skip the end of the previous chunk
+            while line.trim().is_empty() {
+                line.clear();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+            self.remaining = usize::from_str_radix(line.trim(), 16)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            if self.remaining == 0 {
+                self.done = true;
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.remaining);
+        let read = self.reader.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+/// A WebSocket client
+pub(crate) struct TcpConnector;
+
+impl WebSocketConnector for TcpConnector {
+    type Socket = TcpSocket;
+
+    fn connect(&self, url: &Url) -> Result<Self::Socket, Box<dyn StdError + Send + Sync>> {
+        let mut stream = connect(url)?;
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            target(url),
+            url.host_str().unwrap_or(""),
+            WEBSOCKET_KEY
+        )?;
+
+        let mut reader = BufReader::new(stream);
+        let head = read_head(&mut reader)?;
+        check_status(&head, 101)?;
+        if !head.contains(&format!("Sec-WebSocket-Accept: {}\r\n", WEBSOCKET_ACCEPT)) {
+            return Err("Invalid handshake".into());
+        }
+        Ok(TcpSocket { reader })
+    }
+}
+
+/// A WebSocket connection
+pub(crate) struct TcpSocket {
+    reader: BufReader<TcpStream>,
+}
+
+impl WebSocket for TcpSocket {
+    fn receive(&mut self) -> Result<Option<String>, Box<dyn StdError + Send + Sync>> {
+        match read_frame(&mut self.reader)? {
+            (1, payload) => Ok(Some(String::from_utf8(payload)?)),
+            (8, _) => Ok(None),
+            (opcode, _) => Err(format!("Unexpected opcode {}", opcode).into()),
+        }
+    }
+
+    fn send(&mut self, message: String) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        write_frame(self.reader.get_mut(), 1, message.as_bytes(), true)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,428 @@
+//! WebSocket
+//!
+//! With a WebSocket, Mastodon streams events of several streams over a single
+//! connection. The client subscribes to streams by sending messages, and each
+//! event is received as a JSON message, with the name of the stream, the name of
+//! the event, and its payload.
+//!
+//! [`WebSocketStream`] manages the subscriptions, and iterates over the events,
+//! reconnecting and subscribing again when the connection is lost.
+
+use super::{connect_error, is_final, Event, Reconnect, Stream};
+use crate::Error;
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error as StdError;
+use std::thread;
+use url::Url;
+
+/// A WebSocket connection
+///
+/// Implement this trait to use a WebSocket library with [`WebSocketStream`].
+/// Only text messages are used.
+pub trait WebSocket {
+    /// Receive a text message
+    ///
+    /// Returns `None` when the connection is closed.
+    fn receive(&mut self) -> Result<Option<String>, Box<dyn StdError + Send + Sync>>;
+
+    /// Send a text message
+    fn send(&mut self, message: String) -> Result<(), Box<dyn StdError + Send + Sync>>;
+}
+
+/// A trait to open WebSocket connections
+///
+/// When the server refuses the connection, the connector should return an error
+/// created with [`Error::with_status`], so that the stream stops when reconnecting
+/// cannot succeed.
+pub trait WebSocketConnector {
+    /// WebSocket connection
+    type Socket: WebSocket;
+
+    /// Open a connection
+    fn connect(&self, url: &Url) -> Result<Self::Socket, Box<dyn StdError + Send + Sync>>;
+}
+
+/// An event received with a WebSocket
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct StreamEvent {
+    /// Stream of the event
+    ///
+    /// This is the name of the stream, like `hashtag`, followed by its parameter,
+    /// if any.
+    pub stream: Vec<String>,
+    /// Event
+    pub event: Event,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    #[serde(default)]
+    stream: Vec<String>,
+    event: Option<String>,
+    #[serde(default)]
+    payload: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    status: Option<u16>,
+}
+
+/// Parse a WebSocket message
+///
+/// Errors reported by the server, like a missing access token, are mapped
+/// according to their status code, like [`Error::Unauthorized`].
+pub fn parse_message(message: &str) -> Result<StreamEvent, Error> {
+    let message: Message = serde_json::from_str(message)?;
+    if let Some(error) = message.error {
+        return Err(match message.status {
+            Some(status) => Error::with_status(status, Some(error)),
+            None => Error::Transport(error.into()),
+        });
+    }
+    let event = match message.event {
+        Some(event) => event,
+        None => return Err(Error::Transport("Message without event".into())),
+    };
+    let payload = match message.payload {
+        Some(payload) => payload,
+        None if Event::has_payload(&event) => {
+            let error = format!("Message without payload for event {}", event);
+            return Err(Error::Transport(error.into()));
+        }
+        None => String::new(),
+    };
+    Ok(StreamEvent {
+        stream: message.stream,
+        event: Event::decode(&event, &payload)?,
+    })
+}
+
+fn subscription_message(kind: &str, stream: &Stream) -> String {
+    let mut message = json!({ "type": kind, "stream": stream.name() });
+    if let Some((key, value)) = stream.param() {
+        message[key] = value.into();
+    }
+    message.to_string()
+}
+
+/// An iterator over the events of several streams
+///
+/// Events are received with a single WebSocket connection. When the connection
+/// is lost, the stream reconnects according to its [`Reconnect`] policy,
+/// subscribes again to its streams, and the error is returned. The iterator ends
+/// when the maximum number of attempts is reached, or when the connection is
+/// refused with a status like `401 Unauthorized`.
+pub struct WebSocketStream<C>
+where
+    C: WebSocketConnector,
+{
+    connector: C,
+    url: Url,
+    streams: Vec<Stream>,
+    socket: Option<C::Socket>,
+    reconnect: Reconnect,
+    attempts: u32,
+    done: bool,
+}
+
+impl<C> WebSocketStream<C>
+where
+    C: WebSocketConnector,
+{
+    /// Create a stream
+    ///
+    /// `base` is the URL of the streaming API of the instance, usually the URL of
+    /// the instance. An `https` URL is connected with `wss`. The connection is
+    /// opened on the first call to `next`.
+    pub fn new(connector: C, base: &Url, token: Option<&str>) -> Result<Self, Error> {
+        let mut url = base.join("api/v1/streaming")?;
+        let scheme = match url.scheme() {
+            "https" => Some("wss"),
+            "http" => Some("ws"),
+            _ => None,
+        };
+        if let Some(scheme) = scheme {
+            // Changing between special schemes cannot fail
+            let _ = url.set_scheme(scheme);
+        }
+        if let Some(token) = token {
+            url.query_pairs_mut().append_pair("access_token", token);
+        }
+        Ok(WebSocketStream {
+            connector,
+            url,
+            streams: Vec::new(),
+            socket: None,
+            reconnect: Reconnect::default(),
+            attempts: 0,
+            done: false,
+        })
+    }
+
+    /// Set the reconnection policy
+    pub fn with_reconnect(mut self, reconnect: Reconnect) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Subscribed streams
+    pub fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    /// Subscribe to a stream
+    ///
+    /// If the connection is open, the subscription is sent immediately. Otherwise,
+    /// it is sent when the connection is opened.
+    pub fn subscribe(&mut self, stream: Stream) -> Result<(), Error> {
+        if self.streams.contains(&stream) {
+            return Ok(());
+        }
+        let message = subscription_message("subscribe", &stream);
+        self.streams.push(stream);
+        self.send(message)
+    }
+
+    /// Unsubscribe from a stream
+    pub fn unsubscribe(&mut self, stream: &Stream) -> Result<(), Error> {
+        let len = self.streams.len();
+        self.streams.retain(|s| s != stream);
+        if self.streams.len() == len {
+            return Ok(());
+        }
+        self.send(subscription_message("unsubscribe", stream))
+    }
+
+    fn send(&mut self, message: String) -> Result<(), Error> {
+        let result = match &mut self.socket {
+            Some(socket) => socket.send(message),
+            None => return Ok(()),
+        };
+        result.map_err(|error| {
+            self.disconnect();
+            Error::Transport(error)
+        })
+    }
+
+    fn connect(&mut self) -> Result<(), Error> {
+        let mut socket = self.connector.connect(&self.url).map_err(connect_error)?;
+        for stream in &self.streams {
+            socket
+                .send(subscription_message("subscribe", stream))
+                .map_err(Error::Transport)?;
+        }
+        self.socket = Some(socket);
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.socket = None;
+        self.attempts += 1;
+    }
+}
+
+impl<C> Iterator for WebSocketStream<C>
+where
+    C: WebSocketConnector,
+{
+    type Item = Result<StreamEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let socket = match &mut self.socket {
+                Some(socket) => socket,
+                None => {
+                    if self.attempts > 0 {
+                        if !self.reconnect.can_retry(self.attempts - 1) {
+                            return None;
+                        }
+                        thread::sleep(self.reconnect.delay(self.attempts - 1));
+                    }
+                    if let Err(error) = self.connect() {
+                        self.attempts += 1;
+                        self.done = is_final(&error);
+                        return Some(Err(error));
+                    }
+                    continue;
+                }
+            };
+
+            match socket.receive() {
+                Ok(Some(message)) => {
+                    self.attempts = 0;
+                    return Some(parse_message(&message));
+                }
+                Ok(None) => self.disconnect(),
+                Err(error) => {
+                    self.disconnect();
+                    return Some(Err(Error::Transport(error)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stub::{
+        accept_websocket, read_text, write_close, write_error, write_text, Handler, StubServer,
+        TcpConnector,
+    };
+    use super::super::tests::STATUS;
+    use super::*;
+    use std::io::BufReader;
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    fn no_delay(max_attempts: u32) -> Reconnect {
+        Reconnect::new(
+            Duration::from_secs(0),
+            Duration::from_secs(0),
+            Some(max_attempts),
+        )
+    }
+
+    /// Accept a connection subscribing to direct messages, and send a deletion
+    fn direct(id: &'static str) -> Handler {
+        Box::new(move |head: &str, reader: &mut BufReader<TcpStream>| {
+            accept_websocket(head, reader.get_mut());
+            assert_eq!(
+                read_text(reader),
+                r#"{"stream":"direct","type":"subscribe"}"#
+            );
+            let message = json!({ "stream": ["direct"], "event": "delete", "payload": id });
+            write_text(reader.get_mut(), &message.to_string());
+            write_close(reader.get_mut());
+        })
+    }
+
+    #[test]
+    fn test_parse_message() {
+        let message = json!({
+            "stream": ["hashtag", "rust"],
+            "event": "update",
+            "payload": STATUS,
+        });
+        let event = parse_message(&message.to_string()).unwrap();
+        assert_eq!(event.stream, vec!["hashtag", "rust"]);
+        assert!(matches!(event.event, Event::Update(_)));
+
+        let event = parse_message(r#"{"stream":["user"],"event":"delete","payload":"1"}"#).unwrap();
+        assert_eq!(event.event, Event::Delete("1".to_string()));
+
+        let event = parse_message(r#"{"stream":["user"],"event":"filters_changed"}"#).unwrap();
+        assert_eq!(event.event, Event::FiltersChanged);
+        let error = parse_message(r#"{"stream":["user"],"event":"update"}"#).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Transport error: Message without payload for event update"
+        );
+
+        assert!(matches!(
+            parse_message(r#"{"error":"Missing access token","status":401}"#),
+            Err(Error::Unauthorized { message: Some(message) }) if message == "Missing access token"
+        ));
+        assert!(matches!(
+            parse_message(r#"{"error":"Internal error","status":500}"#),
+            Err(Error::Http { status: 500, .. })
+        ));
+        assert!(matches!(
+            parse_message(r#"{"error":"Unknown stream type"}"#),
+            Err(Error::Transport(_))
+        ));
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let server = StubServer::start(vec![Box::new(
+            |head: &str, reader: &mut BufReader<TcpStream>| {
+                accept_websocket(head, reader.get_mut());
+                assert_eq!(read_text(reader), r#"{"stream":"user","type":"subscribe"}"#);
+                assert_eq!(
+                    read_text(reader),
+                    r#"{"stream":"hashtag","tag":"rust","type":"subscribe"}"#
+                );
+                let message = json!({ "stream": ["user"], "event": "update", "payload": STATUS });
+                write_text(reader.get_mut(), &message.to_string());
+                assert_eq!(
+                    read_text(reader),
+                    r#"{"stream":"hashtag","tag":"rust","type":"unsubscribe"}"#
+                );
+                write_close(reader.get_mut());
+            },
+        )]);
+        let mut stream = WebSocketStream::new(TcpConnector, &server.url(), Some("token"))
+            .unwrap()
+            .with_reconnect(no_delay(0));
+        stream.subscribe(Stream::User).unwrap();
+        stream
+            .subscribe(Stream::Hashtag("rust".to_string()))
+            .unwrap();
+
+        let event = stream.next().unwrap().unwrap();
+        assert_eq!(event.stream, vec!["user"]);
+        assert!(matches!(event.event, Event::Update(_)));
+        stream
+            .unsubscribe(&Stream::Hashtag("rust".to_string()))
+            .unwrap();
+        assert_eq!(stream.streams(), &[Stream::User]);
+        assert!(stream.next().is_none());
+
+        let heads = server.join();
+        assert!(heads[0].starts_with("GET /api/v1/streaming?access_token=token HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let server = StubServer::start(vec![
+            direct("1"),
+            Box::new(|_: &str, reader: &mut BufReader<TcpStream>| {
+                write_error(reader.get_mut(), "502 Bad Gateway")
+            }),
+            // Subscribed again after reconnecting
+            direct("2"),
+        ]);
+        let mut stream = WebSocketStream::new(TcpConnector, &server.url(), None)
+            .unwrap()
+            .with_reconnect(no_delay(2));
+        stream.subscribe(Stream::Direct).unwrap();
+
+        let events = stream
+            .map(|event| event.map(|event| event.event).map_err(|e| e.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[..3],
+            [
+                Ok(Event::Delete("1".to_string())),
+                Err("Server error 502: Bad Gateway".to_string()),
+                Ok(Event::Delete("2".to_string())),
+            ]
+        );
+        // The server is gone
+        assert!(events[3..].iter().all(Result::is_err));
+        assert_eq!(server.join().len(), 3);
+    }
+
+    #[test]
+    fn test_final_status() {
+        let server = StubServer::start(vec![Box::new(
+            |_: &str, reader: &mut BufReader<TcpStream>| {
+                write_error(reader.get_mut(), "404 Not Found")
+            },
+        )]);
+        // Reconnects forever by default
+        let mut stream = WebSocketStream::new(TcpConnector, &server.url(), None).unwrap();
+        stream.subscribe(Stream::Direct).unwrap();
+
+        let events = stream.collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], Err(Error::NotFound { .. })));
+        assert_eq!(server.join().len(), 1);
+    }
+}
@@ -3,3 +3,6 @@
 //! This crate tests if Crabodon compiles with MSRV (Rust 1.52.1).
 //! It contains constraints on 3rd party crates to make them compile
 //! with MSRV.
+//!
+//! Optional features are not checked: the `image` feature is exempt from
+//! MSRV, as the `image` crate requires a more recent compiler.